@@ -1,10 +1,45 @@
-use proto::{QuadClient, AddPointRequest, Circle, Point, DeletePointRequest, FindWithinRangeRequest};
+use std::path::{Path, PathBuf};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use config::Config;
+use proto::{QuadClient, AddPointRequest, AddPointsRequest, DeletePointsRequest, Mutation, MutationResult, mutation, Circle, Entry, Point, DeletePointRequest, DeleteByIdRequest, FindWithinRangeRequest, FindWithinRectangleRequest, FindWithinPolygonRequest, FindNearestRequest, GetAllQuadsRequest, CreateTreeRequest, DropTreeRequest, Polygon, Rectangle, UpdatePointRequest};
 use clap::{Parser, Subcommand, Args};
-use tonic::transport::Channel;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
 use anyhow::{Result, anyhow};
 
+/// Wait before the first connection retry
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest wait between connection retries
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
 #[derive(Parser)]
 struct Cli {
+    /// Address of the server, e.g. http://localhost:50051. Defaults to the address the server is
+    /// configured with in its config file and QUADTREE_* environment variables
+    #[arg(long, global = true, env = "QUADTREE_ENDPOINT")]
+    endpoint: Option<String>,
+    /// Server config file to read the default address from, /etc/quadtree/config.ini when omitted
+    #[arg(long, global = true, env = "QUADTREE_CONFIG")]
+    config: Option<String>,
+    /// Seconds to wait for each connection attempt
+    #[arg(long, global = true, default_value_t = 5)]
+    connect_timeout: u64,
+    /// Number of times a failed connection is retried
+    #[arg(long, global = true, default_value_t = 3)]
+    retries: u32,
+    /// CA certificate used to verify the server. Connects over TLS when set
+    #[arg(long, global = true, env = "QUADTREE_CA_CERT")]
+    ca_cert: Option<PathBuf>,
+    /// Client certificate for servers that require mutual TLS
+    #[arg(long, global = true, env = "QUADTREE_CERT", requires = "key")]
+    cert: Option<PathBuf>,
+    /// Private key of the client certificate
+    #[arg(long, global = true, env = "QUADTREE_KEY", requires = "cert")]
+    key: Option<PathBuf>,
+    /// Name of the tree to use, the server's default tree when omitted
+    #[arg(long, global = true, default_value = "")]
+    tree: String,
     #[command(subcommand)]
     command: Commands
 }
@@ -12,13 +47,35 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Adds a new point to the Quad Tree
-    AddPoint(InputPoint),
+    AddPoint(InputEntry),
     /// Deletes a point from the Quad Tree
     DeletePoint(InputPoint),
+    /// Moves the point with the given id to a new location
+    UpdatePoint(InputUpdate),
+    /// Deletes the point with the given id from the Quad Tree
+    DeleteById(InputId),
     /// Find all points within the specified circular region
     FindWithinRange(InputCircle),
+    /// Find all points within the specified circular region, streamed in chunks
+    StreamWithinRange(InputCircle),
+    /// Count the points within the specified circular region
+    CountWithinRange(InputCircle),
+    /// Find all points within the specified rectangular region
+    FindWithinRectangle(InputRectangle),
+    /// Find all points within the specified polygon
+    FindWithinPolygon(InputPolygon),
+    /// Find the k points closest to the specified point
+    Nearest(InputNearest),
     /// Returns all Quad Tree nodes
-    GetAllQuads
+    GetAllQuads,
+    /// Adds or deletes every point listed in a CSV file
+    Import(InputImport),
+    /// Creates a new named Quad Tree
+    CreateTree(InputCreateTree),
+    /// Deletes a named Quad Tree and all of its points
+    DropTree(InputTreeName),
+    /// Lists every Quad Tree on the server
+    ListTrees
 }
 
 #[derive(Args)]
@@ -29,6 +86,32 @@ struct InputPoint {
     y: f64
 }
 
+#[derive(Args)]
+struct InputEntry {
+    #[command(flatten)]
+    point: InputPoint,
+    /// Optional payload stored alongside the point
+    #[arg(long, default_value = "")]
+    payload: String,
+    /// Optional id for the point, assigned by the server when omitted
+    #[arg(long)]
+    id: Option<u64>
+}
+
+#[derive(Args)]
+struct InputId {
+    /// id of the point
+    id: u64
+}
+
+#[derive(Args)]
+struct InputUpdate {
+    /// id of the point
+    id: u64,
+    #[command(flatten)]
+    point: InputPoint
+}
+
 #[derive(Args)]
 struct InputCircle {
     x: f64,
@@ -36,38 +119,148 @@ struct InputCircle {
     radius: f64
 }
 
+#[derive(Args)]
+struct InputRectangle {
+    /// x coordinate of the top left corner
+    top_left_x: f64,
+    /// y coordinate of the top left corner
+    top_left_y: f64,
+    /// x coordinate of the bottom right corner
+    bottom_right_x: f64,
+    /// y coordinate of the bottom right corner
+    bottom_right_y: f64
+}
+
+#[derive(Args)]
+struct InputPolygon {
+    /// x and y coordinates of each vertex, e.g. 0 0 10 0 10 10
+    #[arg(required = true, allow_negative_numbers = true)]
+    coordinates: Vec<f64>
+}
+
+#[derive(Args)]
+struct InputNearest {
+    #[command(flatten)]
+    point: InputPoint,
+    /// Number of points to return
+    k: u32
+}
+
+#[derive(Args)]
+struct InputImport {
+    /// CSV file with one x,y[,payload] point per line
+    file: PathBuf,
+    /// Delete the listed points instead of adding them
+    #[arg(long)]
+    delete: bool,
+    /// Send all points over a single stream instead of in batches
+    #[arg(long)]
+    stream: bool,
+    /// Number of points sent per batch request
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64
+}
+
+#[derive(Args)]
+struct InputTreeName {
+    /// Name of the tree
+    name: String
+}
+
+#[derive(Args)]
+struct InputCreateTree {
+    #[command(flatten)]
+    name: InputTreeName,
+    #[command(flatten)]
+    border: InputRectangle,
+    /// Number of points a node holds before it splits
+    #[arg(long, default_value_t = 10)]
+    capacity: i64,
+    /// Maximum depth of the tree, unlimited when omitted
+    #[arg(long)]
+    max_depth: Option<i64>,
+    /// How points at an existing location are handled: allow, reject or count
+    #[arg(long, default_value = "")]
+    duplicates: String,
+    /// Grow the tree's border to fit points inserted outside of it
+    #[arg(long)]
+    growable: bool
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match &cli.command {
-        Commands::AddPoint(point) => {
+        Commands::AddPoint(entry) => {
             let request = tonic::Request::new(AddPointRequest{
                 point: Some(Point{
-                    x: point.x,
-                    y: point.y
-                })
+                    x: entry.point.x,
+                    y: entry.point.y
+                }),
+                payload: entry.payload.as_bytes().to_vec(),
+                id: entry.id,
+                tree: cli.tree.clone()
             });
 
-            let mut client = get_client().await?;
+            let mut client = get_client(&cli).await?;
 
-            let _response = client.add_point(request)
+            let response = client.add_point(request)
                 .await
-                .map_err(|_x| anyhow!("Failure adding point"))?;
+                .map_err(|e| anyhow!("Failure adding point: {}", e.message()))?;
+
+            let response = response.into_inner();
+
+            println!("{}", response.id);
+
+            if response.grew {
+                println!("Tree bounds grew to fit the point");
+            }
         },
         Commands::DeletePoint(point) => {
             let request = tonic::Request::new(DeletePointRequest{
                 point: Some(Point{
                     x: point.x,
                     y: point.y
-                })
+                }),
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let response = client.delete_point(request)
+                .await
+                .map_err(|e| anyhow!("Failure deleting point: {}", e.message()))?;
+
+            println!("Deleted {} point(s)", response.into_inner().deleted);
+        },
+        Commands::UpdatePoint(update) => {
+            let request = tonic::Request::new(UpdatePointRequest{
+                id: update.id,
+                point: Some(Point{
+                    x: update.point.x,
+                    y: update.point.y
+                }),
+                tree: cli.tree.clone()
             });
 
-            let mut client = get_client().await?;
+            let mut client = get_client(&cli).await?;
 
-            let _response = client.delete_point(request)
+            let _response = client.update_point(request)
                 .await
-                .map_err(|_x| anyhow!("Failure deleting point"))?;
+                .map_err(|e| anyhow!("Failure updating point: {}", e.message()))?;
+        },
+        Commands::DeleteById(input) => {
+            let request = tonic::Request::new(DeleteByIdRequest{
+                id: input.id,
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let _response = client.delete_by_id(request)
+                .await
+                .map_err(|e| anyhow!("Failure deleting point: {}", e.message()))?;
         },
         Commands::FindWithinRange(circle) => {
             let request = tonic::Request::new(FindWithinRangeRequest{
@@ -75,29 +268,152 @@ async fn main() -> Result<()> {
                     x: circle.x,
                     y: circle.y, 
                     radius: circle.radius 
-                })
+                }),
+                tree: cli.tree.clone()
             });
 
-            let mut client = get_client().await?;
+            let mut client = get_client(&cli).await?;
 
             let response = client.find_within_range(request)
                 .await
-                .map_err(|_x| anyhow!("Failure finding within range"))?;
+                .map_err(|e| anyhow!("Failure finding within range: {}", e.message()))?;
 
-            let points = response.into_inner().points;
+            let entries = response.into_inner().entries;
 
-            for point in points {
-                println!("{}, {}", point.x, point.y);
+            for entry in entries {
+                print_entry(&entry);
+            }
+        },
+        Commands::StreamWithinRange(circle) => {
+            let request = tonic::Request::new(FindWithinRangeRequest{
+                circle: Some(Circle{
+                    x: circle.x,
+                    y: circle.y,
+                    radius: circle.radius
+                }),
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let mut stream = client.stream_within_range(request)
+                .await
+                .map_err(|e| anyhow!("Failure streaming within range: {}", e.message()))?
+                .into_inner();
+
+            while let Some(response) = stream.message()
+                .await
+                .map_err(|e| anyhow!("Failure streaming within range: {}", e.message()))? {
+                for entry in response.entries {
+                    print_entry(&entry);
+                }
+            }
+        },
+        Commands::CountWithinRange(circle) => {
+            let request = tonic::Request::new(FindWithinRangeRequest{
+                circle: Some(Circle{
+                    x: circle.x,
+                    y: circle.y,
+                    radius: circle.radius
+                }),
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let response = client.count_within_range(request)
+                .await
+                .map_err(|e| anyhow!("Failure counting within range: {}", e.message()))?;
+
+            println!("{}", response.into_inner().count);
+        },
+        Commands::FindWithinRectangle(rectangle) => {
+            let request = tonic::Request::new(FindWithinRectangleRequest{
+                rectangle: Some(Rectangle{
+                    top_left: Some(Point{
+                        x: rectangle.top_left_x,
+                        y: rectangle.top_left_y
+                    }),
+                    bottom_right: Some(Point{
+                        x: rectangle.bottom_right_x,
+                        y: rectangle.bottom_right_y
+                    })
+                }),
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let response = client.find_within_rectangle(request)
+                .await
+                .map_err(|e| anyhow!("Failure finding within rectangle: {}", e.message()))?;
+
+            let entries = response.into_inner().entries;
+
+            for entry in entries {
+                print_entry(&entry);
+            }
+        },
+        Commands::FindWithinPolygon(polygon) => {
+            if polygon.coordinates.len() % 2 != 0 || polygon.coordinates.len() < 6 {
+                return Err(anyhow!("A polygon needs at least three vertices given as x y pairs"));
+            }
+
+            let request = tonic::Request::new(FindWithinPolygonRequest{
+                polygon: Some(Polygon{
+                    vertices: polygon.coordinates
+                        .chunks(2)
+                        .map(|c| Point{x: c[0], y: c[1]})
+                        .collect()
+                }),
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let response = client.find_within_polygon(request)
+                .await
+                .map_err(|e| anyhow!("Failure finding within polygon: {}", e.message()))?;
+
+            let entries = response.into_inner().entries;
+
+            for entry in entries {
+                print_entry(&entry);
+            }
+        },
+        Commands::Nearest(nearest) => {
+            let request = tonic::Request::new(FindNearestRequest{
+                point: Some(Point{
+                    x: nearest.point.x,
+                    y: nearest.point.y
+                }),
+                k: nearest.k,
+                tree: cli.tree.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            let response = client.find_nearest(request)
+                .await
+                .map_err(|e| anyhow!("Failure finding nearest points: {}", e.message()))?;
+
+            let neighbours = response.into_inner().neighbours;
+
+            for neighbour in neighbours {
+                print!("{}\t", neighbour.distance);
+                print_entry(&neighbour.entry.unwrap_or_default());
             }
         },
         Commands::GetAllQuads => {
-            let request = tonic::Request::new(());
+            let request = tonic::Request::new(GetAllQuadsRequest{
+                tree: cli.tree.clone()
+            });
 
-            let mut client = get_client().await?;
+            let mut client = get_client(&cli).await?;
 
             let response = client.get_all_quads(request)
                 .await
-                .map_err(|_x| anyhow!("Failing getting all quads"))?;
+                .map_err(|e| anyhow!("Failing getting all quads: {}", e.message()))?;
 
             match response.into_inner().quad_node {
                 Some(quad) => {
@@ -107,17 +423,379 @@ async fn main() -> Result<()> {
                     println!("Cannot display quad");
                 }
             }
+        },
+        Commands::Import(import) => {
+            let points = read_points(&import.file, &cli.tree)?;
+            let lines = points.iter().map(|(line, _)| *line).collect::<Vec<usize>>();
+
+            let mut client = get_client(&cli).await?;
+            let mut results: Vec<MutationResult> = vec![];
+
+            if import.stream {
+                let mutations = points
+                    .into_iter()
+                    .map(|(_, request)| to_mutation(request, import.delete))
+                    .collect::<Vec<Mutation>>();
+
+                let response = client.stream_mutations(tokio_stream::iter(mutations))
+                    .await
+                    .map_err(|e| anyhow!("Failure importing points: {}", e.message()))?;
+
+                results.extend(response.into_inner().results);
+            }
+            else {
+                for chunk in points.chunks(import.batch_size as usize) {
+                    let requests = chunk.iter().map(|(_, request)| request.clone());
+
+                    let response = if import.delete {
+                        let request = tonic::Request::new(DeletePointsRequest{
+                            points: requests
+                                .map(|r| DeletePointRequest{point: r.point, tree: r.tree})
+                                .collect(),
+                            tree: cli.tree.clone()
+                        });
+
+                        client.delete_points(request).await
+                    }
+                    else {
+                        let request = tonic::Request::new(AddPointsRequest{
+                            points: requests.collect(),
+                            tree: cli.tree.clone()
+                        });
+
+                        client.add_points(request).await
+                    };
+
+                    let response = response
+                        .map_err(|e| anyhow!("Failure importing points: {}", e.message()))?;
+
+                    results.extend(response.into_inner().results);
+                }
+            }
+
+            let mut failed = 0;
+            for (line, result) in lines.iter().zip(&results) {
+                if !result.ok {
+                    failed += 1;
+                    eprintln!("Line {}: {}", line, result.error);
+                }
+            }
+
+            println!("{} succeeded, {} failed", results.len() - failed, failed);
+        },
+        Commands::CreateTree(input) => {
+            let border = &input.border;
+
+            let request = tonic::Request::new(CreateTreeRequest{
+                name: input.name.name.clone(),
+                border: Some(Rectangle{
+                    top_left: Some(Point{
+                        x: border.top_left_x,
+                        y: border.top_left_y
+                    }),
+                    bottom_right: Some(Point{
+                        x: border.bottom_right_x,
+                        y: border.bottom_right_y
+                    })
+                }),
+                capacity: input.capacity,
+                max_depth: input.max_depth,
+                duplicates: input.duplicates.clone(),
+                growable: input.growable
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            client.create_tree(request)
+                .await
+                .map_err(|e| anyhow!("Failure creating tree: {}", e.message()))?;
+        },
+        Commands::DropTree(input) => {
+            let request = tonic::Request::new(DropTreeRequest{
+                name: input.name.clone()
+            });
+
+            let mut client = get_client(&cli).await?;
+
+            client.drop_tree(request)
+                .await
+                .map_err(|e| anyhow!("Failure dropping tree: {}", e.message()))?;
+        },
+        Commands::ListTrees => {
+            let request = tonic::Request::new(());
+
+            let mut client = get_client(&cli).await?;
+
+            let response = client.list_trees(request)
+                .await
+                .map_err(|e| anyhow!("Failure listing trees: {}", e.message()))?;
+
+            for tree in response.into_inner().trees {
+                let border = tree.border.unwrap_or_default();
+                let top_left = border.top_left.unwrap_or_default();
+                let bottom_right = border.bottom_right.unwrap_or_default();
+
+                println!("{}: ({}, {}) to ({}, {}), capacity {}, {} points",
+                    tree.name, top_left.x, top_left.y, bottom_right.x, bottom_right.y, tree.capacity, tree.count);
+            }
         }
     }
 
     Ok(())
 }
 
-async fn get_client() -> Result<QuadClient<Channel>> {
-    let client = QuadClient::connect("http://[::1]:50051")
-        .await
-        .map_err(|_x| anyhow!("Cannot connect to service"))?;
+fn print_entry(entry: &Entry) {
+    let point = entry.point.clone().unwrap_or_default();
+
+    if entry.payload.is_empty() {
+        println!("{}: {}, {}", entry.id, point.x, point.y);
+    }
+    else {
+        println!("{}: {}, {}, {}", entry.id, point.x, point.y, String::from_utf8_lossy(&entry.payload));
+    }
+}
+
+// Parses x,y[,payload] lines, skipping blank lines and # comments. Each
+// request is returned with its line number for error reporting.
+fn read_points(file: &Path, tree: &str) -> Result<Vec<(usize, AddPointRequest)>> {
+    let contents = std::fs::read_to_string(file)
+        .map_err(|e| anyhow!("Cannot read {}: {}", file.display(), e))?;
+
+    let mut points = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+
+        let mut fields = line.splitn(3, ',');
+
+        let mut coordinate = || -> Result<f64> {
+            let field = fields.next().unwrap_or("").trim();
+
+            return field.parse::<f64>()
+                .map_err(|_x| anyhow!("Line {}: invalid coordinate '{}'", index + 1, field));
+        };
+
+        let x = coordinate()?;
+        let y = coordinate()?;
+        let payload = fields.next().unwrap_or("").as_bytes().to_vec();
+
+        points.push((index + 1, AddPointRequest{
+            point: Some(Point{x, y}),
+            payload,
+            id: None,
+            tree: tree.to_string()
+        }));
+    }
+
+    return Ok(points);
+}
+
+fn to_mutation(request: AddPointRequest, delete: bool) -> Mutation {
+    let kind = if delete {
+        mutation::Kind::Delete(DeletePointRequest{point: request.point, tree: request.tree})
+    }
+    else {
+        mutation::Kind::Add(request)
+    };
+
+    return Mutation{kind: Some(kind)};
+}
+
+// Falls back to the server's config file when no endpoint is given, so a
+// client on the same machine finds the server on whatever address it uses.
+fn endpoint_address(cli: &Cli) -> Result<String> {
+    let tls = cli.ca_cert.is_some() || cli.cert.is_some();
+
+    return resolve_endpoint(cli.endpoint.as_deref(), tls, || {
+        match Config::load(cli.config.as_deref(), &[]) {
+            Ok(config) => return Ok(config),
+            // A config file that was asked for must be readable
+            Err(e) if cli.config.is_some() => return Err(anyhow!("Invalid configuration: {}", e)),
+            Err(_) => return Ok(Config::default())
+        }
+    });
+}
+
+// Picks the URL to connect to. The endpoint, from --endpoint or else
+// QUADTREE_ENDPOINT, wins over the address the server is configured with,
+// which is only loaded when there is no endpoint. A bare address gets an
+// https scheme when TLS is in use and http otherwise.
+fn resolve_endpoint<F: FnOnce() -> Result<Config>>(endpoint: Option<&str>, tls: bool, load_config: F) -> Result<String> {
+    let mut tls = tls;
+
+    let address = match endpoint {
+        Some(endpoint) => endpoint.to_string(),
+        None => {
+            let config = load_config()?;
+            tls = tls || config.tls_cert.is_some();
+
+            let mut address = config.bind_address()?;
+
+            // A server listening on every interface is reached through loopback
+            if address.ip().is_unspecified() {
+                address.set_ip(match address.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST)
+                });
+            }
+
+            address.to_string()
+        }
+    };
+
+    if address.contains("://") {
+        return Ok(address);
+    }
+
+    if tls {
+        return Ok(format!("https://{}", address));
+    }
+
+    return Ok(format!("http://{}", address));
+}
+
+// How long to wait before retrying a failed connection, doubling from
+// INITIAL_BACKOFF with every attempt up to MAX_BACKOFF
+fn retry_delay(attempt: u32) -> Duration {
+    return INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_BACKOFF);
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    return std::fs::read(path).map_err(|e| anyhow!("Cannot read {}: {}", path.display(), e));
+}
+
+// The server is only trusted if its certificate is signed by the given CA
+// and names the host being connected to
+fn client_tls_config(cli: &Cli, host: &str) -> Result<ClientTlsConfig> {
+    let ca_cert = cli.ca_cert.as_ref()
+        .ok_or(anyhow!("--ca-cert is required to verify the server over TLS"))?;
+
+    // IPv6 hosts keep their URL brackets, which are not part of the name
+    let domain_name = host.trim_start_matches('[').trim_end_matches(']');
+
+    let mut tls = ClientTlsConfig::new()
+        .domain_name(domain_name)
+        .ca_certificate(Certificate::from_pem(read_pem(ca_cert)?));
+
+    if let (Some(cert), Some(key)) = (&cli.cert, &cli.key) {
+        tls = tls.identity(Identity::from_pem(read_pem(cert)?, read_pem(key)?));
+    }
+
+    return Ok(tls);
+}
 
-    return Ok(client);
+// Transport errors only say "transport error" themselves, the cause, such as
+// a rejected certificate, is in their source
+fn error_details(error: &tonic::transport::Error) -> String {
+    match std::error::Error::source(error) {
+        Some(source) => return format!("{}: {}", error, source),
+        None => return error.to_string()
+    }
 }
 
+async fn get_client(cli: &Cli) -> Result<QuadClient<Channel>> {
+    let address = endpoint_address(cli)?;
+
+    let mut endpoint = Endpoint::from_shared(address.clone())
+        .map_err(|_x| anyhow!("Invalid endpoint '{}'", address))?
+        .connect_timeout(Duration::from_secs(cli.connect_timeout));
+
+    if address.starts_with("https://") || cli.ca_cert.is_some() || cli.cert.is_some() {
+        let host = endpoint.uri().host().unwrap_or_default().to_string();
+
+        endpoint = endpoint.tls_config(client_tls_config(cli, &host)?)
+            .map_err(|e| anyhow!("Invalid TLS configuration: {}", error_details(&e)))?;
+    }
+
+    let mut attempt = 0;
+
+    loop {
+        match endpoint.connect().await {
+            Ok(channel) => {
+                return Ok(QuadClient::new(channel));
+            },
+            Err(e) => {
+                if attempt >= cli.retries {
+                    return Err(anyhow!("Cannot connect to service at {}: {}", address, error_details(&e)));
+                }
+
+                tokio::time::sleep(retry_delay(attempt)).await;
+
+                attempt += 1;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use clap::Parser;
+    use config::Config;
+
+    use crate::{Cli, endpoint_address, resolve_endpoint, retry_delay};
+
+    fn config(host: &str) -> Config {
+        let mut config = Config::default();
+        config.host = host.to_string();
+        config.port = 6000;
+
+        return config;
+    }
+
+    #[test]
+    fn test_endpoint_wins_over_config() {
+        let address = resolve_endpoint(Some("http://example.com:5000"), false, || Err(anyhow!("config loaded"))).unwrap();
+
+        assert_eq!(address, "http://example.com:5000");
+        assert_eq!(resolve_endpoint(Some("example.com:5000"), true, || Err(anyhow!("config loaded"))).unwrap(), "https://example.com:5000");
+    }
+
+    #[test]
+    fn test_endpoint_from_config() {
+        assert_eq!(resolve_endpoint(None, false, || Ok(config("10.0.0.5"))).unwrap(), "http://10.0.0.5:6000");
+        assert_eq!(resolve_endpoint(None, true, || Ok(config("::1"))).unwrap(), "https://[::1]:6000");
+
+        let mut tls = config("10.0.0.5");
+        tls.tls_cert = Some(String::from("server.pem"));
+        assert_eq!(resolve_endpoint(None, false, || Ok(tls)).unwrap(), "https://10.0.0.5:6000");
+
+        assert!(resolve_endpoint(None, false, || Err(anyhow!("unreadable"))).is_err());
+        assert!(resolve_endpoint(None, false, || Ok(config("localhost"))).is_err());
+    }
+
+    #[test]
+    fn test_unspecified_host_is_reached_through_loopback() {
+        assert_eq!(resolve_endpoint(None, false, || Ok(config("0.0.0.0"))).unwrap(), "http://127.0.0.1:6000");
+        assert_eq!(resolve_endpoint(None, false, || Ok(config("::"))).unwrap(), "http://[::1]:6000");
+    }
+
+    #[test]
+    fn test_endpoint_flag_wins_over_environment() {
+        std::env::set_var("QUADTREE_ENDPOINT", "http://from-env:5000");
+
+        let cli = Cli::parse_from(["client", "list-trees"]);
+        assert_eq!(endpoint_address(&cli).unwrap(), "http://from-env:5000");
+
+        let cli = Cli::parse_from(["client", "--endpoint", "http://from-flag:5000", "list-trees"]);
+        assert_eq!(endpoint_address(&cli).unwrap(), "http://from-flag:5000");
+
+        std::env::remove_var("QUADTREE_ENDPOINT");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let delays = (0..4).map(retry_delay).collect::<Vec<Duration>>();
+
+        assert_eq!(delays, vec![Duration::from_millis(100), Duration::from_millis(200), Duration::from_millis(400), Duration::from_millis(800)]);
+        assert_eq!(retry_delay(7), Duration::from_secs(10));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(10));
+    }
+}
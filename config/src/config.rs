@@ -1,6 +1,44 @@
-use configparser::ini::Ini;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+use crate::{ConfigParsingError, ConfigSource};
+use crate::file_format::FileFormat;
+
+pub const DEFAULT_CONFIG_FILE: &str = "/etc/quadtree/config.ini";
+
+// Every field can be overridden by an environment variable named after it,
+// e.g. QUADTREE_PORT or QUADTREE_SNAPSHOT_PATH
+pub const ENV_PREFIX: &str = "QUADTREE_";
+
+// Kept in step with the deepest max_depth the storage crate will load from a snapshot
+const MAX_DEPTH: i64 = 1024;
+
+// The ini section each key is read from, in the order they are reported
+const KEYS: [(&str, &str); 18] = [
+    ("server", "host"),
+    ("server", "port"),
+    ("quadtree", "top_left_x"),
+    ("quadtree", "top_left_y"),
+    ("quadtree", "bottom_right_x"),
+    ("quadtree", "bottom_right_y"),
+    ("quadtree", "capacity"),
+    ("quadtree", "max_depth"),
+    ("quadtree", "duplicates"),
+    ("quadtree", "growable"),
+    ("persistence", "snapshot_path"),
+    ("persistence", "snapshot_interval"),
+    ("persistence", "wal_path"),
+    ("persistence", "wal_fsync"),
+    ("persistence", "wal_fsync_interval"),
+    ("tls", "tls_cert"),
+    ("tls", "tls_key"),
+    ("tls", "tls_client_ca")
+];
 
 pub struct Config {
+    // IPv4 or IPv6 address the server listens on, read from `host` or `bind_address`
+    pub host: String,
     pub port: i64,
 
     pub top_left_x: f64,
@@ -8,83 +46,387 @@ pub struct Config {
     pub bottom_right_x: f64,
     pub bottom_right_y: f64,
 
-    pub capacity: i64
-}
+    pub capacity: i64,
+    pub max_depth: i64,
+    pub duplicates: String,
+    pub growable: bool,
+
+    // Snapshots are disabled when no path is set
+    pub snapshot_path: Option<String>,
+    pub snapshot_interval: i64,
 
-pub struct ConfigParsingError {
-    pub details: String
+    // Mutations are only logged when a path is set
+    pub wal_path: Option<String>,
+    pub wal_fsync: String,
+    pub wal_fsync_interval: i64,
+
+    // PEM files. The server only uses TLS when a certificate and key are set,
+    // and a client CA makes it require client certificates signed by that CA.
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_client_ca: Option<String>,
+
+    // Keys missing from the map still hold their default value
+    sources: HashMap<String, ConfigSource>
 }
 
-impl ConfigParsingError {
-    fn new(details: &str) -> ConfigParsingError {
-        ConfigParsingError { details: details.to_string() }
+impl Default for Config {
+    fn default() -> Config {
+        return Config { 
+            host: String::from("::1"),
+            port: 50051, 
+            top_left_x: 0.0,
+            top_left_y: 0.0, 
+            bottom_right_x: 10.0,
+            bottom_right_y: 10.0,
+            capacity: 10,
+            max_depth: 24,
+            duplicates: String::from("allow"),
+            growable: false,
+            snapshot_path: None,
+            snapshot_interval: 60,
+            wal_path: None,
+            wal_fsync: String::from("always"),
+            wal_fsync_interval: 1000,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            sources: HashMap::new()
+        }
     }
 }
 
 impl Config {
     pub fn parse(file: &str) -> Result<Config, ConfigParsingError> {
-        let mut config = Ini::new();
+        let config = Config::read_file(file)?;
+        config.validate()?;
+
+        return Ok(config);
+    }
+
+    // The format is picked from the extension: .toml, .yaml or .yml, and INI
+    // for anything else
+    fn read_file(file: &str) -> Result<Config, ConfigParsingError> {
+        let sections = FileFormat::from_path(file).read(file)?;
 
-        let result = config.load(file);
-        let default_config = Config::default();
+        let mut config = Config::default();
 
-        match result {
-            Ok(_) => {
-                let returned_config = Config{
-                    port: config.getint("server", "port").unwrap().unwrap_or(default_config.port),
+        for (section, values) in &sections {
+            if !KEYS.iter().any(|(known, _)| known == section) {
+                return Err(ConfigParsingError::new(section, "", "unknown section"));
+            }
 
-                    top_left_x: config.getfloat("quadtree", "top_left_x").unwrap().unwrap_or(default_config.top_left_x),
-                    top_left_y: config.getfloat("quadtree", "top_left_y").unwrap().unwrap_or(default_config.top_left_y),
-                    bottom_right_x: config.getfloat("quadtree", "bottom_right_x").unwrap().unwrap_or(default_config.bottom_right_x),
-                    bottom_right_y: config.getfloat("quadtree", "bottom_right_y").unwrap().unwrap_or(default_config.bottom_right_y),
+            for (key, value) in values {
+                // `bind_address` is accepted as another name for `host`
+                let name = if section == "server" && key == "bind_address" { "host" } else { key.as_str() };
 
-                    capacity: config.getint("quadtree", "capacity").unwrap().unwrap_or(10)
-                };
+                if !KEYS.iter().any(|(known_section, known_key)| known_section == section && *known_key == name) {
+                    return Err(ConfigParsingError::new(section, key, "unknown key"));
+                }
 
-                return Ok(returned_config);
+                match value {
+                    Some(value) => config.set(name, value, ConfigSource::File)?,
+                    None => return Err(ConfigParsingError::new(section, key, "missing value"))
+                }
             }
-            Err(_) => {
-                return Err(ConfigParsingError::new(format!("Cannot parse config file: {file}").as_str()));
+        }
+
+        return Ok(config);
+    }
+
+    pub fn parse_or_default(file: &str) -> Config {
+        return Config::parse(file).unwrap_or_default();
+    }
+
+    // Layers, lowest precedence first: defaults, the config file, QUADTREE_*
+    // environment variables and finally command line overrides. Without an
+    // explicit file the default one is used if it exists.
+    pub fn load(file: Option<&str>, overrides: &[(String, String)]) -> Result<Config, ConfigParsingError> {
+        return Config::load_from(file, std::env::vars(), overrides);
+    }
+
+    pub fn load_from<I>(file: Option<&str>, env: I, overrides: &[(String, String)]) -> Result<Config, ConfigParsingError>
+    where
+        I: IntoIterator<Item = (String, String)>
+    {
+        let mut config = match file {
+            Some(file) => Config::read_file(file)?,
+            None => {
+                if Path::new(DEFAULT_CONFIG_FILE).exists() {
+                    Config::read_file(DEFAULT_CONFIG_FILE)?
+                }
+                else {
+                    Config::default()
+                }
+            }
+        };
+
+        let env = env.into_iter().collect::<HashMap<String, String>>();
+
+        for key in Config::keys() {
+            let name = format!("{}{}", ENV_PREFIX, key.to_uppercase());
+
+            if let Some(value) = env.get(&name) {
+                config.set(key, value, ConfigSource::Environment)
+                    .map_err(|e| e.with_source(&name))?;
             }
         }
+
+        for (key, value) in overrides {
+            config.set(key, value, ConfigSource::CommandLine)
+                .map_err(|e| e.with_source("--set"))?;
+        }
+
+        config.validate()?;
+
+        return Ok(config);
     }
 
-    pub fn default() -> Config {
-        return Config { 
-            port: 50051, 
-            top_left_x: 0.0,
-            top_left_y: 0.0, 
-            bottom_right_x: 10.0,
-            bottom_right_y: 10.0,
-            capacity: 10 
+    // Checks the values that parse but cannot be used, e.g. an empty border
+    pub fn validate(&self) -> Result<(), ConfigParsingError> {
+        self.bind_address()?;
+
+        let coordinates = [
+            ("top_left_x", self.top_left_x),
+            ("top_left_y", self.top_left_y),
+            ("bottom_right_x", self.bottom_right_x),
+            ("bottom_right_y", self.bottom_right_y)
+        ];
+
+        for (key, value) in coordinates {
+            if !value.is_finite() {
+                return Err(invalid(key, format!("must be a finite number, got {value}")));
+            }
+        }
+
+        if self.bottom_right_x <= self.top_left_x {
+            return Err(invalid("bottom_right_x", format!("must be greater than top_left_x ({}), got {}", self.top_left_x, self.bottom_right_x)));
+        }
+
+        if self.bottom_right_y <= self.top_left_y {
+            return Err(invalid("bottom_right_y", format!("must be greater than top_left_y ({}), got {}", self.top_left_y, self.bottom_right_y)));
+        }
+
+        if self.capacity <= 0 {
+            return Err(invalid("capacity", format!("must be greater than 0, got {}", self.capacity)));
+        }
+
+        if self.max_depth < 0 {
+            return Err(invalid("max_depth", format!("must not be negative, got {}", self.max_depth)));
+        }
+
+        if self.max_depth > MAX_DEPTH {
+            return Err(invalid("max_depth", format!("must be at most {MAX_DEPTH}, got {}", self.max_depth)));
+        }
+
+        if !["allow", "reject", "count"].contains(&self.duplicates.to_lowercase().as_str()) {
+            return Err(invalid("duplicates", format!("expected allow, reject or count, got '{}'", self.duplicates)));
+        }
+
+        if self.snapshot_interval < 0 {
+            return Err(invalid("snapshot_interval", format!("must not be negative, got {}", self.snapshot_interval)));
+        }
+
+        if !["always", "interval", "never"].contains(&self.wal_fsync.to_lowercase().as_str()) {
+            return Err(invalid("wal_fsync", format!("expected always, interval or never, got '{}'", self.wal_fsync)));
         }
+
+        if self.wal_fsync_interval < 0 {
+            return Err(invalid("wal_fsync_interval", format!("must not be negative, got {}", self.wal_fsync_interval)));
+        }
+
+        if self.tls_cert.is_some() && self.tls_key.is_none() {
+            return Err(invalid("tls_key", String::from("must be set along with tls_cert")));
+        }
+
+        if self.tls_key.is_some() && self.tls_cert.is_none() {
+            return Err(invalid("tls_cert", String::from("must be set along with tls_key")));
+        }
+
+        if self.tls_client_ca.is_some() && self.tls_cert.is_none() {
+            return Err(invalid("tls_client_ca", String::from("requires tls_cert and tls_key, mutual TLS needs a server certificate")));
+        }
+
+        return Ok(());
     }
 
-    pub fn parse_or_default(file: &str) -> Config {
-        let parsed_config = Config::parse(file);
+    pub fn keys() -> impl Iterator<Item = &'static str> {
+        return KEYS.iter().map(|(_, key)| *key);
+    }
+
+    pub fn source(&self, key: &str) -> ConfigSource {
+        return self.sources.get(key).copied().unwrap_or(ConfigSource::Default);
+    }
+
+    pub fn set(&mut self, key: &str, value: &str, source: ConfigSource) -> Result<(), ConfigParsingError> {
+        match key {
+            "host" => self.host = value.trim().to_string(),
+            "port" => self.port = parse_value(key, value, "an integer")?,
+            "top_left_x" => self.top_left_x = parse_value(key, value, "a number")?,
+            "top_left_y" => self.top_left_y = parse_value(key, value, "a number")?,
+            "bottom_right_x" => self.bottom_right_x = parse_value(key, value, "a number")?,
+            "bottom_right_y" => self.bottom_right_y = parse_value(key, value, "a number")?,
+            "capacity" => self.capacity = parse_value(key, value, "an integer")?,
+            "max_depth" => self.max_depth = parse_value(key, value, "an integer")?,
+            "duplicates" => self.duplicates = value.trim().to_string(),
+            "growable" => self.growable = parse_value(key, &value.to_lowercase(), "true or false")?,
+            "snapshot_path" => self.snapshot_path = optional_value(value),
+            "snapshot_interval" => self.snapshot_interval = parse_value(key, value, "an integer")?,
+            "wal_path" => self.wal_path = optional_value(value),
+            "wal_fsync" => self.wal_fsync = value.trim().to_string(),
+            "wal_fsync_interval" => self.wal_fsync_interval = parse_value(key, value, "an integer")?,
+            "tls_cert" => self.tls_cert = optional_value(value),
+            "tls_key" => self.tls_key = optional_value(value),
+            "tls_client_ca" => self.tls_client_ca = optional_value(value),
+            _ => {
+                return Err(ConfigParsingError::new("", key, "unknown key"));
+            }
+        }
+
+        self.sources.insert(key.to_string(), source);
+
+        return Ok(());
+    }
+
+    // The effective value of a key, formatted for display
+    pub fn get(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "host" => self.host.clone(),
+            "port" => self.port.to_string(),
+            "top_left_x" => self.top_left_x.to_string(),
+            "top_left_y" => self.top_left_y.to_string(),
+            "bottom_right_x" => self.bottom_right_x.to_string(),
+            "bottom_right_y" => self.bottom_right_y.to_string(),
+            "capacity" => self.capacity.to_string(),
+            "max_depth" => self.max_depth.to_string(),
+            "duplicates" => self.duplicates.clone(),
+            "growable" => self.growable.to_string(),
+            "snapshot_path" => self.snapshot_path.clone().unwrap_or_default(),
+            "snapshot_interval" => self.snapshot_interval.to_string(),
+            "wal_path" => self.wal_path.clone().unwrap_or_default(),
+            "wal_fsync" => self.wal_fsync.clone(),
+            "wal_fsync_interval" => self.wal_fsync_interval.to_string(),
+            "tls_cert" => self.tls_cert.clone().unwrap_or_default(),
+            "tls_key" => self.tls_key.clone().unwrap_or_default(),
+            "tls_client_ca" => self.tls_client_ca.clone().unwrap_or_default(),
+            _ => return None
+        };
 
-        match parsed_config {
-            Ok(r) => r,
-            Err(_) => Config::default()
+        return Some(value);
+    }
+
+    pub fn bind_address(&self) -> Result<SocketAddr, ConfigParsingError> {
+        // IPv6 addresses may be written in their bracketed URL form
+        let host = self.host.trim().trim_start_matches('[').trim_end_matches(']');
+
+        let ip = match host.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                return Err(invalid("host", format!("expected an IPv4 or IPv6 address such as 0.0.0.0 or ::1, got '{}'", self.host)));
+            }
+        };
+
+        let port = match u16::try_from(self.port) {
+            Ok(port) => port,
+            Err(_) => {
+                return Err(invalid("port", format!("expected a number from 0 to 65535, got {}", self.port)));
+            }
+        };
+
+        return Ok(SocketAddr::new(ip, port));
+    }
+}
+
+fn section_of(key: &str) -> &'static str {
+    return KEYS.iter()
+        .find(|(_, known)| *known == key)
+        .map(|(section, _)| *section)
+        .unwrap_or("");
+}
+
+fn invalid(key: &str, problem: String) -> ConfigParsingError {
+    return ConfigParsingError::new(section_of(key), key, &problem);
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str, expected: &str) -> Result<T, ConfigParsingError> {
+    match value.trim().parse::<T>() {
+        Ok(value) => return Ok(value),
+        Err(_) => {
+            return Err(invalid(key, format!("expected {expected}, got '{value}'")));
         }
     }
 }
 
+// An empty value unsets an optional key, e.g. QUADTREE_WAL_PATH=
+fn optional_value(value: &str) -> Option<String> {
+    let value = value.trim();
+
+    if value.is_empty() {
+        return None;
+    }
+
+    return Some(value.to_string());
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::Config;
+    use crate::{Config, ConfigParsingError, ConfigSource};
 
-    #[test]
-    fn test_parse_full_config() {
-        let config = Config::parse("mocks/full_config.ini");
-        let config_result = config.ok().unwrap();
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        return vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    }
 
+    // Every full_config mock holds the same values in a different format
+    fn assert_full_config(config_result: &Config) {
+        assert_eq!(config_result.host, "0.0.0.0");
         assert_eq!(config_result.port, 5000);
         assert_eq!(config_result.top_left_x, 1.0);
         assert_eq!(config_result.top_left_y, 1.0);
         assert_eq!(config_result.bottom_right_x, 3.0);
         assert_eq!(config_result.bottom_right_y, 3.0);
         assert_eq!(config_result.capacity, 5);
+        assert_eq!(config_result.max_depth, 8);
+        assert_eq!(config_result.duplicates, "reject");
+        assert!(config_result.growable);
+        assert_eq!(config_result.snapshot_path.as_deref(), Some("/var/lib/quadtree/tree.snapshot"));
+        assert_eq!(config_result.snapshot_interval, 30);
+        assert_eq!(config_result.wal_path.as_deref(), Some("/var/lib/quadtree/tree.wal"));
+        assert_eq!(config_result.wal_fsync, "interval");
+        assert_eq!(config_result.wal_fsync_interval, 200);
+        assert_eq!(config_result.tls_cert.as_deref(), Some("/etc/quadtree/server.pem"));
+        assert_eq!(config_result.tls_key.as_deref(), Some("/etc/quadtree/server.key"));
+        assert_eq!(config_result.tls_client_ca.as_deref(), Some("/etc/quadtree/clients.pem"));
+    }
+
+    #[test]
+    fn test_parse_full_config() {
+        let config = Config::parse("mocks/full_config.ini");
+        let config_result = config.ok().unwrap();
+
+        assert_full_config(&config_result);
+    }
+
+    #[test]
+    fn test_parse_toml_config() {
+        let config = Config::parse("mocks/full_config.toml");
+
+        assert_full_config(&config.ok().unwrap());
+    }
+
+    #[test]
+    fn test_parse_yaml_config() {
+        let config = Config::parse("mocks/full_config.yaml");
+
+        assert_full_config(&config.ok().unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid_nested_config() {
+        let error = Config::parse("mocks/unknown_key_config.toml").err().unwrap();
+        assert_eq!(error.to_string(), "[quadtree] max_dpeth: unknown key");
+
+        let error = Config::parse("mocks/invalid_value_config.yaml").err().unwrap();
+        assert_eq!(error.to_string(), "[quadtree] capacity: expected a single value, not a list or table");
     }
 
     #[test]
@@ -92,12 +434,130 @@ mod tests {
         let config = Config::parse("mocks/partial_config.ini");
         let config_result = config.ok().unwrap();
 
+        assert_eq!(config_result.host, "::1");
         assert_eq!(config_result.port, 5000);
         assert_eq!(config_result.top_left_x, 1.0);
         assert_eq!(config_result.top_left_y, 0.0);
         assert_eq!(config_result.bottom_right_x, 3.0);
         assert_eq!(config_result.bottom_right_y, 10.0);
         assert_eq!(config_result.capacity, 5);
+        assert_eq!(config_result.max_depth, 24);
+        assert_eq!(config_result.duplicates, "allow");
+        assert!(!config_result.growable);
+        assert_eq!(config_result.snapshot_path, None);
+        assert_eq!(config_result.snapshot_interval, 60);
+        assert_eq!(config_result.wal_path, None);
+        assert_eq!(config_result.wal_fsync, "always");
+        assert_eq!(config_result.wal_fsync_interval, 1000);
+        assert_eq!(config_result.tls_cert, None);
+        assert_eq!(config_result.tls_key, None);
+        assert_eq!(config_result.tls_client_ca, None);
+    }
+
+    #[test]
+    fn test_bind_address() {
+        let config = Config::parse("mocks/full_config.ini").ok().unwrap();
+        assert_eq!(config.bind_address().ok().unwrap().to_string(), "0.0.0.0:5000");
+
+        let config = Config { host: String::from("[::]"), ..Config::default() };
+        assert_eq!(config.bind_address().ok().unwrap().to_string(), "[::]:50051");
+
+        let config = Config { host: String::from("localhost"), ..Config::default() };
+        let error = config.bind_address().err().unwrap();
+        assert_eq!((error.section.as_str(), error.key.as_str()), ("server", "host"));
+        assert!(error.problem.contains("got 'localhost'"));
+
+        let config = Config { port: 70000, ..Config::default() };
+        assert!(config.bind_address().is_err());
+    }
+
+    #[test]
+    fn test_load_precedence() {
+        let overrides = vec![(String::from("capacity"), String::from("9"))];
+        let vars = env(&[
+            ("QUADTREE_PORT", "6000"),
+            ("QUADTREE_CAPACITY", "7"),
+            ("QUADTREE_WAL_PATH", ""),
+            ("QUADTREE_ENDPOINT", "http://localhost:50051")
+        ]);
+
+        let config = Config::load_from(Some("mocks/full_config.ini"), vars, &overrides).ok().unwrap();
+
+        assert_eq!(config.capacity, 9);
+        assert_eq!(config.source("capacity"), ConfigSource::CommandLine);
+        assert_eq!(config.port, 6000);
+        assert_eq!(config.source("port"), ConfigSource::Environment);
+        assert_eq!(config.wal_path, None);
+        assert_eq!(config.source("wal_path"), ConfigSource::Environment);
+        assert_eq!(config.host, "0.0.0.0");
+        assert_eq!(config.source("host"), ConfigSource::File);
+
+        let config = Config::load_from(Some("mocks/partial_config.ini"), env(&[]), &[]).ok().unwrap();
+
+        assert_eq!(config.source("port"), ConfigSource::File);
+        assert_eq!(config.source("snapshot_interval"), ConfigSource::Default);
+        assert_eq!(config.get("snapshot_interval").as_deref(), Some("60"));
+    }
+
+    #[test]
+    fn test_load_invalid_overrides() {
+        let overrides = vec![(String::from("colour"), String::from("blue"))];
+        let config = Config::load_from(Some("mocks/full_config.ini"), env(&[]), &overrides);
+        assert_eq!(config.err().unwrap().to_string(), "colour: unknown key (from --set)");
+
+        let vars = env(&[("QUADTREE_GROWABLE", "maybe")]);
+        let config = Config::load_from(Some("mocks/full_config.ini"), vars, &[]);
+        assert_eq!(config.err().unwrap().to_string(), "[quadtree] growable: expected true or false, got 'maybe' (from QUADTREE_GROWABLE)");
+
+        // Overrides are validated together with the file they are applied to
+        let overrides = vec![(String::from("bottom_right_y"), String::from("0.5"))];
+        let config = Config::load_from(Some("mocks/full_config.ini"), env(&[]), &overrides);
+        assert_eq!(config.err().unwrap().key, "bottom_right_y");
+
+        let config = Config::load_from(Some("nonexistent_config.ini"), env(&[]), &[]);
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let error = Config::parse("mocks/unknown_key_config.ini").err().unwrap();
+
+        assert_eq!(error, ConfigParsingError {
+            section: String::from("server"),
+            key: String::from("prot"),
+            problem: String::from("unknown key")
+        });
+        assert_eq!(error.to_string(), "[server] prot: unknown key");
+    }
+
+    #[test]
+    fn test_invalid_value() {
+        let error = Config::parse("mocks/invalid_value_config.ini").err().unwrap();
+
+        assert_eq!(error.to_string(), "[server] port: expected an integer, got 'abc'");
+    }
+
+    #[test]
+    fn test_invalid_bounds() {
+        let error = Config::parse("mocks/invalid_bounds_config.ini").err().unwrap();
+        assert_eq!(error.to_string(), "[quadtree] bottom_right_x: must be greater than top_left_x (5), got 3");
+
+        let config = Config { capacity: 0, ..Config::default() };
+        assert_eq!(config.validate().err().unwrap().to_string(), "[quadtree] capacity: must be greater than 0, got 0");
+
+        let config = Config { max_depth: 2000, ..Config::default() };
+        assert_eq!(config.validate().err().unwrap().to_string(), "[quadtree] max_depth: must be at most 1024, got 2000");
+
+        let config = Config { duplicates: String::from("replace"), ..Config::default() };
+        assert_eq!(config.validate().err().unwrap().key, "duplicates");
+
+        let config = Config { tls_cert: Some(String::from("server.pem")), ..Config::default() };
+        assert_eq!(config.validate().err().unwrap().to_string(), "[tls] tls_key: must be set along with tls_cert");
+
+        let config = Config { tls_client_ca: Some(String::from("clients.pem")), ..Config::default() };
+        assert_eq!(config.validate().err().unwrap().key, "tls_client_ca");
+
+        assert!(Config::default().validate().is_ok());
     }
 
     #[test]
@@ -0,0 +1,51 @@
+use std::error::Error;
+use std::fmt;
+
+// Names the offending `[section] key` when the problem is with a single
+// value. Both are empty for errors about the config file as a whole.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigParsingError {
+    pub section: String,
+    pub key: String,
+    pub problem: String
+}
+
+impl ConfigParsingError {
+    pub(crate) fn new(section: &str, key: &str, problem: &str) -> ConfigParsingError {
+        return ConfigParsingError {
+            section: section.to_string(),
+            key: key.to_string(),
+            problem: problem.to_string()
+        };
+    }
+
+    pub(crate) fn file(problem: &str) -> ConfigParsingError {
+        return ConfigParsingError::new("", "", problem);
+    }
+
+    // Notes where a value other than the config file came from
+    pub(crate) fn with_source(mut self, source: &str) -> ConfigParsingError {
+        self.problem = format!("{} (from {})", self.problem, source);
+        return self;
+    }
+}
+
+impl fmt::Display for ConfigParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.section.is_empty() && self.key.is_empty() {
+            return write!(f, "{}", self.problem);
+        }
+
+        if self.section.is_empty() {
+            return write!(f, "{}: {}", self.key, self.problem);
+        }
+
+        if self.key.is_empty() {
+            return write!(f, "[{}]: {}", self.section, self.problem);
+        }
+
+        return write!(f, "[{}] {}: {}", self.section, self.key, self.problem);
+    }
+}
+
+impl Error for ConfigParsingError {}
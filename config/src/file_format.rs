@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use configparser::ini::Ini;
+use crate::ConfigParsingError;
+
+// Every format is flattened to the same section -> key -> value map, which
+// is how INI files are read, so they are all validated the same way. A key
+// without a value maps to None.
+pub(crate) type Sections = HashMap<String, HashMap<String, Option<String>>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FileFormat {
+    Ini,
+    Toml,
+    Yaml
+}
+
+impl FileFormat {
+    // Files without a recognised extension are read as INI, as they always have been
+    pub(crate) fn from_path(file: &str) -> FileFormat {
+        match Path::new(file).extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => return FileFormat::Toml,
+            Some("yaml") | Some("yml") => return FileFormat::Yaml,
+            _ => return FileFormat::Ini
+        }
+    }
+
+    pub(crate) fn read(&self, file: &str) -> Result<Sections, ConfigParsingError> {
+        match self {
+            FileFormat::Ini => return read_ini(file),
+            FileFormat::Toml => return read_toml(file),
+            FileFormat::Yaml => return read_yaml(file)
+        }
+    }
+}
+
+fn cannot_parse(file: &str, error: impl std::fmt::Display) -> ConfigParsingError {
+    return ConfigParsingError::file(format!("Cannot parse config file {file}: {error}").as_str());
+}
+
+fn outside_section(key: &str) -> ConfigParsingError {
+    return ConfigParsingError::new("", key, "must be inside a section such as [server]");
+}
+
+fn not_a_value(section: &str, key: &str) -> ConfigParsingError {
+    return ConfigParsingError::new(section, key, "expected a single value, not a list or table");
+}
+
+fn read_ini(file: &str) -> Result<Sections, ConfigParsingError> {
+    let mut ini = Ini::new();
+
+    let mut sections = ini.load(file).map_err(|e| cannot_parse(file, e))?;
+
+    // Keys above the first section header are read into a "default" section
+    if let Some(values) = sections.remove("default") {
+        if let Some(key) = values.keys().next() {
+            return Err(outside_section(key));
+        }
+    }
+
+    return Ok(sections);
+}
+
+fn read_toml(file: &str) -> Result<Sections, ConfigParsingError> {
+    let contents = fs::read_to_string(file).map_err(|e| cannot_parse(file, e))?;
+    let table = contents.parse::<toml::Table>().map_err(|e| cannot_parse(file, e))?;
+
+    let mut sections = Sections::new();
+
+    for (section, values) in table {
+        let values = match values {
+            toml::Value::Table(values) => values,
+            _ => return Err(outside_section(&section))
+        };
+
+        let mut keys = HashMap::new();
+
+        for (key, value) in values {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => return Err(not_a_value(&section, &key))
+            };
+
+            keys.insert(key, Some(value));
+        }
+
+        sections.insert(section, keys);
+    }
+
+    return Ok(sections);
+}
+
+fn read_yaml(file: &str) -> Result<Sections, ConfigParsingError> {
+    let contents = fs::read_to_string(file).map_err(|e| cannot_parse(file, e))?;
+    let document = serde_yaml::from_str::<serde_yaml::Value>(&contents).map_err(|e| cannot_parse(file, e))?;
+
+    let mut sections = Sections::new();
+
+    let mapping = match document {
+        serde_yaml::Value::Mapping(mapping) => mapping,
+        // An empty document sets nothing
+        serde_yaml::Value::Null => return Ok(sections),
+        _ => return Err(cannot_parse(file, "expected a mapping of sections"))
+    };
+
+    for (section, values) in mapping {
+        let section = yaml_key(file, section)?;
+
+        let values = match values {
+            serde_yaml::Value::Mapping(values) => values,
+            serde_yaml::Value::Null => serde_yaml::Mapping::new(),
+            _ => return Err(outside_section(&section))
+        };
+
+        let mut keys = HashMap::new();
+
+        for (key, value) in values {
+            let key = yaml_key(file, key)?;
+
+            let value = match value {
+                serde_yaml::Value::String(value) => Some(value),
+                serde_yaml::Value::Number(value) => Some(value.to_string()),
+                serde_yaml::Value::Bool(value) => Some(value.to_string()),
+                serde_yaml::Value::Null => None,
+                _ => return Err(not_a_value(&section, &key))
+            };
+
+            keys.insert(key, value);
+        }
+
+        sections.insert(section, keys);
+    }
+
+    return Ok(sections);
+}
+
+fn yaml_key(file: &str, key: serde_yaml::Value) -> Result<String, ConfigParsingError> {
+    match key {
+        serde_yaml::Value::String(key) => return Ok(key),
+        _ => return Err(cannot_parse(file, "section and key names must be strings"))
+    }
+}
@@ -1,4 +1,9 @@
 mod config;
+mod config_parsing_error;
+mod config_source;
+mod file_format;
 
 pub use config::Config;
-pub use config::ConfigParsingError;
+pub use config_parsing_error::ConfigParsingError;
+pub use config::{DEFAULT_CONFIG_FILE, ENV_PREFIX};
+pub use config_source::ConfigSource;
@@ -2,7 +2,8 @@ mod quad {
     tonic::include_proto!("quad");
 }
 
-pub use quad::{AddPointRequest, DeletePointRequest, Circle, FindWithinRangeRequest, FindWithinRangeResponse, GetAllQuadsResponse, Point, QuadNode, Rectangle};
+pub use quad::{AddPointRequest, AddPointResponse, AddPointsRequest, BulkInsertResponse, DeletePointsRequest, Mutation, MutationResult, MutationsResponse, DeletePointRequest, DeletePointResponse, DeleteByIdRequest, UpdatePointRequest, Circle, Entry, FindWithinRangeRequest, FindWithinRangeResponse, CountWithinRangeResponse, FindWithinRectangleRequest, FindWithinPolygonRequest, FindNearestRequest, FindNearestResponse, Neighbour, GetAllQuadsRequest, GetAllQuadsResponse, CreateTreeRequest, DropTreeRequest, TreeInfo, ListTreesResponse, Point, Polygon, QuadNode, Rectangle};
+pub use quad::mutation;
 pub use quad::quad_server::{Quad, QuadServer};
 pub use quad::quad_client::QuadClient;
 
@@ -1,63 +1,570 @@
+mod persistence;
+mod reload;
+mod tls;
+mod tree;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
-use config::Config;
-use tonic::{Request, Response, Status, transport::Server};
-use storage::{Quad as InMemoryQuad, Point, Circle};
-use proto::{AddPointRequest, GetAllQuadsResponse, DeletePointRequest, FindWithinRangeRequest, FindWithinRangeResponse, Quad, QuadServer, QuadNode, Rectangle, Point as ProtoPoint};
+use std::time::Duration;
+use clap::Parser;
+use config::{Config, DEFAULT_CONFIG_FILE};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming, transport::Server};
+use storage::{Quad as InMemoryQuad, Point, Circle, DuplicatePolicy, Entry, FsyncPolicy, InsertError, MAX_DEPTH, Polygon, Rectangle as InMemoryRectangle, WalRecord};
+use proto::{AddPointRequest, AddPointResponse, AddPointsRequest, BulkInsertResponse, DeletePointsRequest, Mutation, MutationResult, MutationsResponse, mutation, GetAllQuadsRequest, GetAllQuadsResponse, CreateTreeRequest, DropTreeRequest, TreeInfo, ListTreesResponse, DeletePointRequest, DeletePointResponse, DeleteByIdRequest, UpdatePointRequest, FindWithinRangeRequest, FindWithinRangeResponse, CountWithinRangeResponse, FindWithinRectangleRequest, FindWithinPolygonRequest, FindNearestRequest, FindNearestResponse, Neighbour, Quad, QuadServer, QuadNode, Rectangle, Point as ProtoPoint};
+
+use crate::persistence::Persistence;
+use crate::reload::Reloader;
+use crate::tls::server_tls_config;
+use crate::tree::{Tree, DEFAULT_TREE};
+
+/// Points are stored alongside an opaque payload supplied by the caller
+type Payload = Vec<u8>;
+
+type Trees = Arc<RwLock<HashMap<String, Arc<Tree>>>>;
+
+/// Number of entries sent per message by the streaming queries
+const STREAM_CHUNK_SIZE: usize = 1000;
+
+/// How long a streaming query waits for the client to make room for the next
+/// chunk before giving up on it
+const STREAM_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the log sync task looks for an interval fsync policy while
+/// another policy is in force
+const SYNC_POLICY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shortest pause between rounds of the log sync task, for an interval of 0
+const MIN_SYNC_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Values are taken from, highest precedence first: --set overrides, QUADTREE_*
+/// environment variables, the config file, then built in defaults
+#[derive(Parser)]
+struct Cli {
+    /// Config file to load, /etc/quadtree/config.ini when omitted. Files ending in .toml, .yaml
+    /// or .yml are read in that format, anything else as INI
+    #[arg(long, env = "QUADTREE_CONFIG")]
+    config: Option<String>,
+    /// Overrides a config value, e.g. --set port=5000. May be repeated
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>
+}
+
+fn parse_override(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((key, value)) => return Ok((key.trim().to_string(), value.to_string())),
+        None => return Err(format!("expected KEY=VALUE, got '{}'", input))
+    }
+}
+
+fn log_config(config: &Config, file: Option<&str>) {
+    match file {
+        Some(file) => println!("Loaded config file {}", file),
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => println!("Loaded config file {}", DEFAULT_CONFIG_FILE),
+        None => println!("No config file found at {}, using defaults", DEFAULT_CONFIG_FILE)
+    }
+
+    for key in Config::keys() {
+        println!("  {} = {} ({})", key, config.get(key).unwrap_or_default(), config.source(key));
+    }
+}
+
+// A default tree restored from its snapshot keeps the bounds and settings it
+// was saved with, so configured values that differ are pointed out
+fn log_saved_settings(config: &Config, quad: &InMemoryQuad<Payload>) {
+    let saved = [
+        ("top_left_x", quad.border.top_left.x.to_string()),
+        ("top_left_y", quad.border.top_left.y.to_string()),
+        ("bottom_right_x", quad.border.bottom_right.x.to_string()),
+        ("bottom_right_y", quad.border.bottom_right.y.to_string()),
+        ("capacity", quad.capacity().to_string()),
+        ("max_depth", quad.max_depth().to_string()),
+        ("duplicates", format!("{:?}", quad.duplicate_policy()).to_lowercase()),
+        ("growable", quad.growable().to_string())
+    ];
+
+    for (key, value) in saved {
+        let configured = config.get(key).unwrap_or_default();
+
+        if configured.to_lowercase() != value {
+            println!("Ignoring {} = {} ({}), the default tree keeps {} from its snapshot", key, configured, config.source(key), value);
+        }
+    }
+}
+
+fn to_proto_entry(entry: &Entry<Payload>) -> proto::Entry {
+    return proto::Entry{
+        point: Some(ProtoPoint{
+            x: entry.point.x,
+            y: entry.point.y
+        }),
+        payload: entry.value.clone(),
+        id: entry.id,
+        count: entry.count
+    }
+}
+
+// Sends the entries within the circle a chunk at a time. The matching ids are
+// collected first, then each chunk is read under its own read lock, which is
+// released before the chunk is sent, so a slow client never holds up writers.
+// Entries deleted or moved out of the circle meanwhile are skipped. A client
+// that goes away ends the stream, and one that stops reading gets the returned
+// error once it reads again.
+fn stream_entries(tree: &Tree, circle: &Circle, sender: &mpsc::Sender<Result<FindWithinRangeResponse, Status>>) -> Result<(), Status> {
+    let runtime = tokio::runtime::Handle::current();
+
+    let ids = match tree.quad.read() {
+        Ok(value) => value.query_iter(circle).map(|e| e.id).collect::<Vec<u64>>(),
+        Err(e) => {
+            println!("Error acquiring read lock {}", e);
+            return Err(Status::internal("Internal Error"));
+        }
+    };
+
+    for chunk in ids.chunks(STREAM_CHUNK_SIZE) {
+        let entries = match tree.quad.read() {
+            Ok(value) => chunk
+                .iter()
+                .filter_map(|id| value.get_by_id(*id))
+                .filter(|e| circle.contains(&e.point))
+                .map(to_proto_entry)
+                .collect::<Vec<proto::Entry>>(),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        };
+
+        if entries.is_empty() {
+            continue
+        }
+
+        match runtime.block_on(tokio::time::timeout(STREAM_SEND_TIMEOUT, sender.send(Ok(to_range_response(entries))))) {
+            Ok(Ok(())) => {},
+            Ok(Err(_)) => return Ok(()),
+            Err(_) => {
+                println!("Client too slow, cancelling stream");
+                return Err(Status::deadline_exceeded("Client did not read the stream in time"));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn to_range_response(entries: Vec<proto::Entry>) -> FindWithinRangeResponse {
+    return FindWithinRangeResponse {
+        points: entries.iter().filter_map(|e| e.point.clone()).collect(),
+        entries
+    };
+}
+
+fn to_proto_rectangle(rectangle: &InMemoryRectangle) -> Rectangle {
+    return Rectangle{
+        top_left: Some(ProtoPoint{
+            x: rectangle.top_left.x,
+            y: rectangle.top_left.y
+        }),
+        bottom_right: Some(ProtoPoint{
+            x: rectangle.bottom_right.x,
+            y: rectangle.bottom_right.y
+        })
+    }
+}
+
+// Rebuilds the nested node tree from the flat walk. Nodes arrive parents
+// first with children in top left, top right, bottom left, bottom right
+// order, so each finished node fills the next free slot of its parent.
+fn to_quad_node(quad: &InMemoryQuad<Payload>) -> Option<QuadNode> {
+    let mut stack: Vec<(i64, QuadNode)> = vec![];
+
+    for node in quad.nodes() {
+        while stack.last().is_some_and(|(depth, _)| *depth >= node.depth) {
+            attach_to_parent(&mut stack);
+        }
+
+        stack.push((node.depth, QuadNode{
+            border: Some(to_proto_rectangle(node.border)),
+            top_left: None,
+            top_right: None,
+            bottom_left: None,
+            bottom_right: None,
+            is_child: node.depth > 0,
+            points: node.entries
+                .iter()
+                .map(|e| ProtoPoint{
+                    x: e.point.x,
+                    y: e.point.y
+                })
+                .collect()
+        }));
+    }
+
+    while stack.len() > 1 {
+        attach_to_parent(&mut stack);
+    }
+
+    return stack.pop().map(|(_, node)| node);
+}
+
+fn attach_to_parent(stack: &mut Vec<(i64, QuadNode)>) {
+    let (_, child) = stack.pop().unwrap();
+    let (_, parent) = stack.last_mut().unwrap();
+
+    let slot = [&mut parent.top_left, &mut parent.top_right, &mut parent.bottom_left, &mut parent.bottom_right]
+        .into_iter()
+        .find(|slot| slot.is_none())
+        .unwrap();
+
+    *slot = Some(Box::new(child));
+}
+
+fn tree_name(name: &str) -> &str {
+    if name.is_empty() {
+        return DEFAULT_TREE
+    }
+
+    return name;
+}
+
+fn mutation_tree(mutation: &Mutation) -> &str {
+    match &mutation.kind {
+        Some(mutation::Kind::Add(request)) => return &request.tree,
+        Some(mutation::Kind::Delete(request)) => return &request.tree,
+        None => return ""
+    }
+}
+
+// Snapshots every tree. Each is snapshotted under its own read lock, so
+// writers to other trees carry on meanwhile.
+async fn save_snapshots(trees: Trees) {
+    let trees = match trees.read() {
+        Ok(value) => value.values().cloned().collect::<Vec<Arc<Tree>>>(),
+        Err(e) => {
+            println!("Error acquiring read lock {}", e);
+            return
+        }
+    };
+
+    for tree in trees {
+        let result = tokio::task::spawn_blocking(move || tree.save_snapshot()).await;
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => println!("Error writing snapshot {}", e),
+            Err(e) => println!("Error writing snapshot {}", e)
+        }
+    }
+}
+
+// Commits under an interval fsync policy only sync once the interval has
+// passed, so the writes before a quiet spell would wait for the next write.
+// This syncs them once they are due instead. The policy can change on reload,
+// so it is read again every round.
+async fn sync_logs(trees: Trees, persistence: Arc<RwLock<Persistence>>) {
+    loop {
+        let interval = match persistence.read() {
+            Ok(value) => match value.fsync_policy {
+                FsyncPolicy::Interval(interval) => interval.max(MIN_SYNC_INTERVAL),
+                _ => SYNC_POLICY_CHECK_INTERVAL
+            },
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return
+            }
+        };
+
+        tokio::time::sleep(interval).await;
+
+        let trees = match trees.read() {
+            Ok(value) => value.values().cloned().collect::<Vec<Arc<Tree>>>(),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return
+            }
+        };
+
+        for tree in trees {
+            let result = tokio::task::spawn_blocking(move || tree.sync_log()).await;
+
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => println!("Error syncing the write-ahead log {}", e),
+                Err(e) => println!("Error syncing the write-ahead log {}", e)
+            }
+        }
+    }
+}
+
+fn fsync_policy(config: &Config) -> Result<FsyncPolicy, String> {
+    match config.wal_fsync.to_lowercase().as_str() {
+        "always" => return Ok(FsyncPolicy::Always),
+        "interval" => return Ok(FsyncPolicy::Interval(Duration::from_millis(config.wal_fsync_interval.max(0) as u64))),
+        "never" => return Ok(FsyncPolicy::Never),
+        _ => return Err(format!("Unknown fsync policy: {}", config.wal_fsync))
+    }
+}
+
+fn add_record(request: &AddPointRequest) -> Option<WalRecord<Payload>> {
+    let point = request.point.as_ref()?;
+
+    return Some(WalRecord::Insert {
+        id: request.id,
+        point: Point{
+            x: point.x,
+            y: point.y
+        },
+        value: request.payload.clone()
+    });
+}
+
+fn delete_record(request: &DeletePointRequest) -> Option<WalRecord<Payload>> {
+    let point = request.point.as_ref()?;
+
+    return Some(WalRecord::Delete {
+        point: Point{
+            x: point.x,
+            y: point.y
+        }
+    });
+}
+
+fn out_of_range(point: &Point, border: &InMemoryRectangle) -> Status {
+    return Status::out_of_range(format!(
+        "Point ({}, {}) is outside the tree bounds ({}, {}) to ({}, {})",
+        point.x, point.y,
+        border.top_left.x, border.top_left.y,
+        border.bottom_right.x, border.bottom_right.y
+    ));
+}
+
+fn insert_error_status(error: InsertError, point: &Point, border: &InMemoryRectangle) -> Status {
+    match error {
+        InsertError::OutOfBounds => return out_of_range(point, border),
+        InsertError::Duplicate(id) => return Status::already_exists(format!("Point ({}, {}) is already occupied by entry {id}", point.x, point.y)),
+        InsertError::IdInUse(id) => return Status::already_exists(format!("Id {id} is already in use")),
+        InsertError::IdReserved(id) => return Status::invalid_argument(format!("Id {id} is reserved"))
+    }
+}
+
+fn apply_add(quad: &mut InMemoryQuad<Payload>, request: AddPointRequest) -> Result<AddPointResponse, Status> {
+    let point = match request.point {
+        Some(p) => Point{
+            x: p.x,
+            y: p.y
+        },
+        None => {
+            println!("Invalid input");
+            return Err(Status::invalid_argument("Invalid input"));
+        }
+    };
+
+    let border = quad.border;
+
+    let result = match request.id {
+        Some(id) => quad.insert_with_id(id, &point, request.payload),
+        None => quad.insert(&point, request.payload)
+    };
+
+    let grew = quad.border != border;
+
+    match result {
+        Ok(id) => return Ok(AddPointResponse{ id, grew }),
+        Err(e) => return Err(insert_error_status(e, &point, &quad.border))
+    }
+}
+
+fn apply_delete(quad: &mut InMemoryQuad<Payload>, request: DeletePointRequest) -> Result<DeletePointResponse, Status> {
+    let point = match request.point {
+        Some(p) => Point{
+            x: p.x,
+            y: p.y
+        },
+        None => {
+            println!("Invalid input");
+            return Err(Status::invalid_argument("Invalid input"));
+        }
+    };
+
+    if !quad.border.contains(&point) {
+        return Err(out_of_range(&point, &quad.border));
+    }
+
+    let deleted = quad.delete(&point).len() as u64;
+
+    return Ok(DeletePointResponse{ deleted });
+}
 
 #[derive(Debug, Default)]
 pub struct QuadService {
-    in_memory_quad: Arc<RwLock<InMemoryQuad>>
+    trees: Trees,
+    // Shared with the config reloader, which may change the fsync policy
+    persistence: Arc<RwLock<Persistence>>
 }
 
 #[tonic::async_trait]
 impl Quad for QuadService {
-    async fn add_point(&self, request: Request<AddPointRequest>) -> Result<Response<()>, Status> {
-        let point = request.into_inner().point;
+    async fn add_point(&self, request: Request<AddPointRequest>) -> Result<Response<AddPointResponse>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
 
-        match point {
-            Some(p) => {
-                let point = Point{
-                    x: p.x,
-                    y: p.y
-                };
+        let quad = &tree.quad;
+        let lock = quad.write();
 
-                let quad = self.in_memory_quad.as_ref();
-                let lock = quad.write();
+        match lock {
+            Ok(mut value) => {
+                tree.log(|| add_record(&request).into_iter().collect())?;
 
-                match lock {
-                    Ok(mut value) => {
-                        value.insert(&point);
-                        return Ok(Response::new(()));
-                    },
-                    Err(e) => {
-                        println!("Error acquiring write lock {}", e);
-                        return Err(Status::internal("Internal Error"));
-                    }
+                return apply_add(&mut value, request).map(Response::new);
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    async fn bulk_insert(&self, request: Request<Streaming<AddPointRequest>>) -> Result<Response<BulkInsertResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut entries = vec![];
+        let mut name: Option<String> = None;
+
+        while let Some(request) = stream.message().await? {
+            if request.id.is_some() {
+                return Err(Status::invalid_argument("Ids cannot be chosen in a bulk insert"));
+            }
+
+            match &name {
+                Some(n) if tree_name(n) != tree_name(&request.tree) => {
+                    return Err(Status::invalid_argument("All points in a bulk insert must target the same tree"));
                 }
+                Some(_) => {}
+                None => name = Some(request.tree.clone())
             }
-            None => {
-                println!("Invalid input");
-                return Err(Status::invalid_argument("Invalid input"));
+
+            match request.point {
+                Some(p) => entries.push((Point{x: p.x, y: p.y}, request.payload)),
+                None => {
+                    println!("Invalid input");
+                    return Err(Status::invalid_argument("Invalid input"));
+                }
+            }
+        }
+
+        let tree = self.tree(name.as_deref().unwrap_or(""))?;
+        let quad = &tree.quad;
+        let lock = quad.write();
+
+        match lock {
+            Ok(mut value) => {
+                tree.log(|| entries
+                    .iter()
+                    .map(|(point, payload)| WalRecord::Insert{id: None, point: *point, value: payload.clone()})
+                    .collect())?;
+
+                let results = value.insert_bulk(entries);
+                let inserted = results.iter().filter(|r| r.is_ok()).count() as u64;
+
+                let response = BulkInsertResponse {
+                    inserted,
+                    rejected: results.len() as u64 - inserted
+                };
+                return Ok(Response::new(response));
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    async fn add_points(&self, request: Request<AddPointsRequest>) -> Result<Response<MutationsResponse>, Status> {
+        let request = request.into_inner();
+        let mutations = request.points
+            .into_iter()
+            .map(|p| Mutation{kind: Some(mutation::Kind::Add(p))})
+            .collect();
+
+        return self.apply_mutations(&request.tree, mutations);
+    }
+
+    async fn delete_points(&self, request: Request<DeletePointsRequest>) -> Result<Response<MutationsResponse>, Status> {
+        let request = request.into_inner();
+        let mutations = request.points
+            .into_iter()
+            .map(|p| Mutation{kind: Some(mutation::Kind::Delete(p))})
+            .collect();
+
+        return self.apply_mutations(&request.tree, mutations);
+    }
+
+    async fn stream_mutations(&self, request: Request<Streaming<Mutation>>) -> Result<Response<MutationsResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut mutations = vec![];
+
+        while let Some(mutation) = stream.message().await? {
+            mutations.push(mutation);
+        }
+
+        // A stream has no header of its own, so the first mutation picks the tree
+        let name = mutations.first().map(mutation_tree).unwrap_or("").to_string();
+
+        return self.apply_mutations(&name, mutations);
+    }
+
+    async fn delete_point(&self, request: Request<DeletePointRequest>) -> Result<Response<DeletePointResponse>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
+
+        let quad = &tree.quad;
+        let lock = quad.write();
+
+        match lock {
+            Ok(mut value) => {
+                tree.log(|| delete_record(&request).into_iter().collect())?;
+
+                return apply_delete(&mut value, request).map(Response::new);
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
             }
         }
     }
 
-    async fn delete_point(&self, request: Request<DeletePointRequest>) -> Result<Response<()>, Status> {
-        let point = request.into_inner().point;
+    async fn update_point(&self, request: Request<UpdatePointRequest>) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
 
-        match point {
+        match request.point {
             Some(p) => {
                 let point = Point{
                     x: p.x,
                     y: p.y
                 };
 
-                let quad = self.in_memory_quad.as_ref();
+                let quad = &tree.quad;
                 let lock = quad.write();
 
                 match lock {
                     Ok(mut value) => {
-                        value.delete(&point);
+                        if value.get_by_id(request.id).is_none() {
+                            return Err(Status::not_found(format!("No point with id {}", request.id)));
+                        }
+
+                        tree.log(|| vec![WalRecord::Move{id: request.id, point}])?;
+
+                        if !value.move_by_id(request.id, &point) {
+                            // Out of bounds, or occupied while duplicates are rejected
+                            if value.search(&point).is_some() {
+                                return Err(Status::already_exists(format!("Point ({}, {}) is already occupied", point.x, point.y)));
+                            }
+
+                            return Err(out_of_range(&point, &value.border));
+                        }
+
                         return Ok(Response::new(()));
                     },
                     Err(e) => {
@@ -73,8 +580,38 @@ impl Quad for QuadService {
         }
     }
 
+    async fn delete_by_id(&self, request: Request<DeleteByIdRequest>) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        let tree = self.tree(&request.tree)?;
+
+        let quad = &tree.quad;
+        let lock = quad.write();
+
+        match lock {
+            Ok(mut value) => {
+                if value.get_by_id(id).is_none() {
+                    return Err(Status::not_found(format!("No point with id {id}")));
+                }
+
+                tree.log(|| vec![WalRecord::DeleteById{id}])?;
+
+                match value.delete_by_id(id) {
+                    Some(_) => return Ok(Response::new(())),
+                    None => return Err(Status::not_found(format!("No point with id {id}")))
+                }
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
     async fn find_within_range(&self, request: Request<FindWithinRangeRequest>) -> Result<Response<FindWithinRangeResponse>, Status> {
-        let circle = request.into_inner().circle;
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
+        let circle = request.circle;
 
         match circle {
             Some(c) => {
@@ -86,22 +623,20 @@ impl Quad for QuadService {
                     radius: c.radius
                 };
 
-                let quad = self.in_memory_quad.as_ref();
-                let lock = quad.write();
+                let quad = &tree.quad;
+                let lock = quad.read();
 
                 match lock {
                     Ok(value) => {
-                        let points = value.find_within_range(&circle)
-                            .iter()
-                            .map(|p| proto::Point{x: p.x, y: p.y})
+                        let entries = value.find_within_range(&circle)
+                            .into_iter()
+                            .map(to_proto_entry)
                             .collect();
-                        let response = FindWithinRangeResponse { 
-                            points
-                        };
+                        let response = to_range_response(entries);
                         return Ok(Response::new(response));
                     }
                     Err(e) => {
-                        println!("Error acquiring write lock {}", e);
+                        println!("Error acquiring read lock {}", e);
                         return Err(Status::internal("Internal Error"));
                     }
                 }
@@ -113,146 +648,912 @@ impl Quad for QuadService {
         }
     }
 
-    async fn get_all_quads(&self, _request: Request<()>) -> Result<Response<GetAllQuadsResponse>, Status> {
-        let quad = self.in_memory_quad.as_ref();
-        let lock = quad.read();
+    type StreamWithinRangeStream = ReceiverStream<Result<FindWithinRangeResponse, Status>>;
 
-        match lock {
-            Ok(value) => {
-                let mut target_quad = proto::QuadNode{
-                    border: None,
-                    top_left: None,
-                    top_right: None,
-                    bottom_left: None,
-                    bottom_right: None,
-                    is_child: false,
-                    points: vec![]
-                };
-                self.recursive_search(&mut target_quad, &value);
+    async fn stream_within_range(&self, request: Request<FindWithinRangeRequest>) -> Result<Response<Self::StreamWithinRangeStream>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
 
-                return Ok(Response::new(GetAllQuadsResponse{quad_node: Some(target_quad)}));
-            }
-            Err(e) => {
-                println!("Error acquiring write lock {}", e);
-                return Err(Status::internal("Internal Error"));
+        let circle = match request.circle {
+            Some(c) => Circle{
+                center: Point {
+                    x: c.x,
+                    y: c.y
+                },
+                radius: c.radius
+            },
+            None => {
+                println!("Invalid input");
+                return Err(Status::invalid_argument("Invalid input"));
             }
-        }
-    }
-}
+        };
 
-impl QuadService {
-    fn recursive_search(&self, target_quad: &mut QuadNode, source_quad: &InMemoryQuad) {
-        for point in &source_quad.points {
-            target_quad.points.push(proto::Point{
-                x: point.x,
-                y: point.y
-            });
-        }
-
-        target_quad.border = Some(Rectangle{
-            top_left: Some(ProtoPoint{
-                x: source_quad.border.top_left.x,
-                y: source_quad.border.top_left.y
-            }),
-            bottom_right: Some(ProtoPoint{
-                x: source_quad.border.bottom_right.x,
-                y: source_quad.border.bottom_right.y
-            })
+        let (sender, receiver) = mpsc::channel(4);
+
+        // Entries are read on a blocking thread a chunk at a time, so at most
+        // a few chunks are buffered no matter how many points the circle covers
+        tokio::task::spawn_blocking(move || {
+            if let Err(status) = stream_entries(&tree, &circle, &sender) {
+                let _ = sender.blocking_send(Err(status));
+            }
         });
 
-        if source_quad.top_left_quad.is_some() {
-            target_quad.top_left = Some(Box::new(QuadNode{
-                border: None,
-                top_left: None,
-                top_right: None,
-                bottom_left: None,
-                bottom_right: None,
-                points: vec![],
-                is_child: true
-            }));
-
-            self.recursive_search(target_quad.top_left.as_mut().unwrap(), source_quad.top_left_quad.as_ref().unwrap());
-        }
-
-        if source_quad.top_right_quad.is_some() {
-            target_quad.top_right = Some(Box::new(QuadNode{
-                border: None,
-                top_left: None,
-                top_right: None,
-                bottom_left: None,
-                bottom_right: None,
-                points: vec![],
-                is_child: true
-            }));
-
-            self.recursive_search(target_quad.top_right.as_mut().unwrap(), source_quad.top_right_quad.as_ref().unwrap());
-        }
-
-        if source_quad.bottom_left_quad.is_some() {
-            target_quad.bottom_left = Some(Box::new(QuadNode{
-                border: None,
-                top_left: None,
-                top_right: None,
-                bottom_left: None,
-                bottom_right: None,
-                points: vec![],
-                is_child: true
-            }));
-
-            self.recursive_search(target_quad.bottom_left.as_mut().unwrap(), source_quad.bottom_left_quad.as_ref().unwrap());
-        }
-
-        if source_quad.bottom_right_quad.is_some() {
-            target_quad.bottom_right = Some(Box::new(QuadNode{
-                border: None,
-                top_left: None,
-                top_right: None,
-                bottom_left: None,
-                bottom_right: None,
-                points: vec![],
-                is_child: true
-            }));
-
-            self.recursive_search(target_quad.bottom_right.as_mut().unwrap(), source_quad.bottom_right_quad.as_ref().unwrap());
-        }
+        return Ok(Response::new(ReceiverStream::new(receiver)));
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let parsed_config = Config::parse("/etc/quadtree/config.ini");
-    let config = match parsed_config {
-        Ok(r) => r,
-        Err(_) => Config::default()
-    };
-
-    let port = config.port;
-
-    let addr = format!("[::1]:{port}").parse().unwrap();
 
-    let top_left = Point { 
-        x: config.top_left_x,
-        y: config.top_left_y
-    };
-    let bottom_right = Point {
-        x: config.bottom_right_x,
-        y: config.bottom_right_y
-    };
+    async fn count_within_range(&self, request: Request<FindWithinRangeRequest>) -> Result<Response<CountWithinRangeResponse>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
+        let circle = request.circle;
 
-    let quad = InMemoryQuad::from(top_left, bottom_right, config.capacity);
+        match circle {
+            Some(c) => {
+                let circle = Circle{
+                    center: Point {
+                        x: c.x,
+                        y: c.y
+                    },
+                    radius: c.radius
+                };
 
-    println!("Quad server listening on {}", addr);
+                let quad = &tree.quad;
+                let lock = quad.read();
+
+                match lock {
+                    Ok(value) => {
+                        let response = CountWithinRangeResponse {
+                            count: value.count_within(&circle) as u64
+                        };
+                        return Ok(Response::new(response));
+                    }
+                    Err(e) => {
+                        println!("Error acquiring read lock {}", e);
+                        return Err(Status::internal("Internal Error"));
+                    }
+                }
+            }
+            None => {
+                println!("Invalid input");
+                return Err(Status::invalid_argument("Invalid input"));
+            }
+        }
+    }
+
+    async fn find_within_rectangle(&self, request: Request<FindWithinRectangleRequest>) -> Result<Response<FindWithinRangeResponse>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
+        let rectangle = request.rectangle;
+
+        match rectangle {
+            Some(Rectangle{top_left: Some(top_left), bottom_right: Some(bottom_right)}) => {
+                let rectangle = InMemoryRectangle::from(
+                    &Point{x: top_left.x, y: top_left.y},
+                    &Point{x: bottom_right.x, y: bottom_right.y}
+                );
+
+                let quad = &tree.quad;
+                let lock = quad.read();
+
+                match lock {
+                    Ok(value) => {
+                        let entries = value.find_within_rectangle(&rectangle)
+                            .into_iter()
+                            .map(to_proto_entry)
+                            .collect();
+                        let response = to_range_response(entries);
+                        return Ok(Response::new(response));
+                    }
+                    Err(e) => {
+                        println!("Error acquiring read lock {}", e);
+                        return Err(Status::internal("Internal Error"));
+                    }
+                }
+            }
+            _ => {
+                println!("Invalid input");
+                return Err(Status::invalid_argument("Invalid input"));
+            }
+        }
+    }
+
+    async fn find_within_polygon(&self, request: Request<FindWithinPolygonRequest>) -> Result<Response<FindWithinRangeResponse>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
+        let polygon = request.polygon;
+
+        match polygon {
+            Some(p) if p.vertices.len() >= 3 => {
+                let polygon = Polygon::from(p.vertices
+                    .iter()
+                    .map(|v| Point{x: v.x, y: v.y})
+                    .collect());
+
+                let quad = &tree.quad;
+                let lock = quad.read();
+
+                match lock {
+                    Ok(value) => {
+                        let entries = value.find_within_polygon(&polygon)
+                            .into_iter()
+                            .map(to_proto_entry)
+                            .collect();
+                        let response = to_range_response(entries);
+                        return Ok(Response::new(response));
+                    }
+                    Err(e) => {
+                        println!("Error acquiring read lock {}", e);
+                        return Err(Status::internal("Internal Error"));
+                    }
+                }
+            }
+            _ => {
+                println!("Invalid input");
+                return Err(Status::invalid_argument("Invalid input"));
+            }
+        }
+    }
+
+    async fn find_nearest(&self, request: Request<FindNearestRequest>) -> Result<Response<FindNearestResponse>, Status> {
+        let request = request.into_inner();
+        let tree = self.tree(&request.tree)?;
+
+        match request.point {
+            Some(p) => {
+                let point = Point{
+                    x: p.x,
+                    y: p.y
+                };
+
+                let quad = &tree.quad;
+                let lock = quad.read();
+
+                match lock {
+                    Ok(value) => {
+                        let neighbours = value.nearest(&point, request.k as usize)
+                            .into_iter()
+                            .map(|(entry, distance)| Neighbour{
+                                entry: Some(to_proto_entry(entry)),
+                                distance
+                            })
+                            .collect();
+                        let response = FindNearestResponse {
+                            neighbours
+                        };
+                        return Ok(Response::new(response));
+                    }
+                    Err(e) => {
+                        println!("Error acquiring read lock {}", e);
+                        return Err(Status::internal("Internal Error"));
+                    }
+                }
+            }
+            None => {
+                println!("Invalid input");
+                return Err(Status::invalid_argument("Invalid input"));
+            }
+        }
+    }
+
+    async fn get_all_quads(&self, request: Request<GetAllQuadsRequest>) -> Result<Response<GetAllQuadsResponse>, Status> {
+        let tree = self.tree(&request.into_inner().tree)?;
+        let quad = &tree.quad;
+        let lock = quad.read();
+
+        match lock {
+            Ok(value) => {
+                return Ok(Response::new(GetAllQuadsResponse{quad_node: to_quad_node(&value)}));
+            }
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    async fn create_tree(&self, request: Request<CreateTreeRequest>) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+
+        if !Tree::is_valid_name(&request.name) {
+            return Err(Status::invalid_argument("Tree names must be 1 to 64 letters, digits, '-' or '_'"));
+        }
+
+        let (top_left, bottom_right) = match request.border {
+            Some(Rectangle{top_left: Some(tl), bottom_right: Some(br)}) => (Point{x: tl.x, y: tl.y}, Point{x: br.x, y: br.y}),
+            _ => {
+                println!("Invalid input");
+                return Err(Status::invalid_argument("Invalid input"));
+            }
+        };
+
+        if ![top_left.x, top_left.y, bottom_right.x, bottom_right.y].iter().all(|c| c.is_finite()) {
+            return Err(Status::invalid_argument("The tree bounds must be finite"));
+        }
+
+        if top_left.x >= bottom_right.x || top_left.y >= bottom_right.y {
+            return Err(Status::invalid_argument("The top left corner must be above and left of the bottom right corner"));
+        }
+
+        if request.capacity <= 0 {
+            return Err(Status::invalid_argument("Capacity must be positive"));
+        }
+
+        if request.max_depth.is_some_and(|d| !(0..=MAX_DEPTH).contains(&d)) {
+            return Err(Status::invalid_argument(format!("Max depth must be between 0 and {MAX_DEPTH}")));
+        }
+
+        let duplicate_policy = match request.duplicates.as_str() {
+            "" => DuplicatePolicy::default(),
+            d => d.parse::<DuplicatePolicy>().map_err(Status::invalid_argument)?
+        };
+
+        let mut quad = InMemoryQuad::from(top_left, bottom_right, request.capacity)
+            .with_duplicate_policy(duplicate_policy)
+            .with_growable(request.growable);
+
+        if let Some(max_depth) = request.max_depth {
+            quad = quad.with_max_depth(max_depth);
+        }
+
+        let persistence = match self.persistence.read() {
+            Ok(value) => value.clone(),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        };
+
+        // Named trees are restored from their snapshots, so without them a
+        // named tree would be lost on restart while the default tree survives
+        if persistence.wal_path.is_some() && persistence.snapshot_path.is_none() {
+            return Err(Status::failed_precondition("Named trees can only be persisted when snapshot_path is set"));
+        }
+
+        let lock = self.trees.write();
+
+        match lock {
+            Ok(mut value) => {
+                if value.contains_key(&request.name) {
+                    return Err(Status::already_exists(format!("Tree {} already exists", request.name)));
+                }
+
+                match Tree::create(&request.name, &persistence, quad) {
+                    Ok(tree) => {
+                        value.insert(request.name, Arc::new(tree));
+                        return Ok(Response::new(()));
+                    }
+                    Err(e) => {
+                        println!("Error creating tree {}", e);
+                        return Err(Status::internal("Internal Error"));
+                    }
+                }
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    async fn drop_tree(&self, request: Request<DropTreeRequest>) -> Result<Response<()>, Status> {
+        let name = request.into_inner().name;
+
+        if tree_name(&name) == DEFAULT_TREE {
+            return Err(Status::failed_precondition("The default tree cannot be dropped"));
+        }
+
+        let lock = self.trees.write();
+
+        match lock {
+            Ok(mut value) => {
+                match value.remove(&name) {
+                    Some(tree) => {
+                        if let Err(e) = tree.discard() {
+                            println!("Error removing files of tree {} {}", name, e);
+                        }
+
+                        return Ok(Response::new(()));
+                    },
+                    None => return Err(Status::not_found(format!("No tree named {name}")))
+                }
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    async fn list_trees(&self, _request: Request<()>) -> Result<Response<ListTreesResponse>, Status> {
+        let trees = match self.trees.read() {
+            Ok(value) => value.values().cloned().collect::<Vec<Arc<Tree>>>(),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        };
+
+        let mut infos = vec![];
+
+        for tree in trees {
+            match tree.quad.read() {
+                Ok(value) => {
+                    infos.push(TreeInfo{
+                        name: tree.name.clone(),
+                        border: Some(to_proto_rectangle(&value.border)),
+                        capacity: value.capacity(),
+                        count: value.len() as u64
+                    });
+                },
+                Err(e) => {
+                    println!("Error acquiring read lock {}", e);
+                    return Err(Status::internal("Internal Error"));
+                }
+            }
+        }
+
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+
+        return Ok(Response::new(ListTreesResponse{ trees: infos }));
+    }
+}
+
+impl QuadService {
+    fn tree(&self, name: &str) -> Result<Arc<Tree>, Status> {
+        let name = tree_name(name);
+        let lock = self.trees.read();
+
+        match lock {
+            Ok(value) => {
+                match value.get(name) {
+                    Some(tree) => return Ok(tree.clone()),
+                    None => return Err(Status::not_found(format!("No tree named {name}")))
+                }
+            },
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    // Applies every mutation under a single write lock. A failing mutation is
+    // reported in its result and does not stop the ones after it.
+    fn apply_mutations(&self, name: &str, mutations: Vec<Mutation>) -> Result<Response<MutationsResponse>, Status> {
+        let tree = self.tree(name)?;
+        let quad = &tree.quad;
+        let lock = quad.write();
+
+        // Mutations naming some other tree fail on their own
+        let targets_tree = |m: &Mutation| {
+            let target = mutation_tree(m);
+            return target.is_empty() || tree_name(target) == tree.name;
+        };
+
+        match lock {
+            Ok(mut value) => {
+                tree.log(|| mutations
+                    .iter()
+                    .filter(|m| targets_tree(m))
+                    .filter_map(|m| match &m.kind {
+                        Some(mutation::Kind::Add(request)) => add_record(request),
+                        Some(mutation::Kind::Delete(request)) => delete_record(request),
+                        None => None
+                    })
+                    .collect())?;
+
+                let results = mutations
+                    .into_iter()
+                    .map(|m| match m.kind {
+                        _ if !targets_tree(&m) => MutationResult{error: String::from("Mutation targets a different tree than its batch"), ..Default::default()},
+                        Some(mutation::Kind::Add(request)) => {
+                            match apply_add(&mut value, request) {
+                                Ok(r) => MutationResult{ok: true, id: r.id, grew: r.grew, ..Default::default()},
+                                Err(e) => MutationResult{error: e.message().to_string(), ..Default::default()}
+                            }
+                        },
+                        Some(mutation::Kind::Delete(request)) => {
+                            match apply_delete(&mut value, request) {
+                                Ok(r) => MutationResult{ok: true, deleted: r.deleted, ..Default::default()},
+                                Err(e) => MutationResult{error: e.message().to_string(), ..Default::default()}
+                            }
+                        },
+                        None => MutationResult{error: String::from("Invalid input"), ..Default::default()}
+                    })
+                    .collect();
+
+                return Ok(Response::new(MutationsResponse{ results }));
+            },
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
+    let config = match Config::load(cli.config.as_deref(), &cli.overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    log_config(&config, cli.config.as_deref());
+
+    let addr = config.bind_address()?;
+
+    let top_left = Point { 
+        x: config.top_left_x,
+        y: config.top_left_y
+    };
+    let bottom_right = Point {
+        x: config.bottom_right_x,
+        y: config.bottom_right_y
+    };
+
+    let duplicate_policy = config.duplicates.parse::<DuplicatePolicy>()?;
+
+    let persistence = Persistence{
+        snapshot_path: config.snapshot_path.as_ref().map(PathBuf::from),
+        wal_path: config.wal_path.as_ref().map(PathBuf::from),
+        fsync_policy: fsync_policy(&config)?
+    };
+
+    let trees = persistence.open_trees(|| {
+        InMemoryQuad::from(top_left, bottom_right, config.capacity)
+            .with_max_depth(config.max_depth)
+            .with_duplicate_policy(duplicate_policy)
+            .with_growable(config.growable)
+    })?;
+
+    if persistence.snapshot_path(DEFAULT_TREE).is_some_and(|path| path.exists()) {
+        match trees[DEFAULT_TREE].quad.read() {
+            Ok(value) => log_saved_settings(&config, &value),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+            }
+        }
+    }
+
+    let trees: Trees = Arc::new(RwLock::new(trees));
+
+    // The interval can change on reload, so it is read from a watch channel
+    let (snapshot_interval, mut interval_changes) = watch::channel(config.snapshot_interval);
+
+    if persistence.snapshot_path.is_some() {
+        let trees = trees.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let seconds = *interval_changes.borrow_and_update();
+
+                // An interval of 0 only snapshots on shutdown
+                if seconds > 0 {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(seconds as u64)) => {
+                            save_snapshots(trees.clone()).await;
+                        }
+                        changed = interval_changes.changed() => {
+                            if changed.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                else if interval_changes.changed().await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    let tls = server_tls_config(&config)?;
+    let mut builder = Server::builder();
+
+    match tls {
+        Some(tls) => {
+            builder = builder.tls_config(tls)?;
+
+            if config.tls_client_ca.is_some() {
+                println!("Requiring client certificates signed by {}", config.tls_client_ca.as_deref().unwrap_or_default());
+            }
+        }
+        None => {
+            println!("TLS is not configured, serving plaintext");
+        }
+    }
+
+    let wal_enabled = persistence.wal_path.is_some();
+    let persistence = Arc::new(RwLock::new(persistence));
+
+    if wal_enabled {
+        tokio::spawn(sync_logs(trees.clone(), persistence.clone()));
+    }
+
+    let reloader = Reloader::new(cli.config, cli.overrides, config, trees.clone(), persistence.clone(), snapshot_interval);
+    tokio::spawn(reloader.run());
+
+    println!("Quad server listening on {}", addr);
 
     let service = QuadService{
-        in_memory_quad: Arc::new(RwLock::new(quad))
+        trees: trees.clone(),
+        persistence
     };
 
     let server = QuadServer::new(service);
 
-    Server::builder()
+    builder
         .add_service(server)
-        .serve(addr)
+        .serve_with_shutdown(addr, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
         .await?;
 
+    save_snapshots(trees).await;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    use proto::{AddPointRequest, AddPointsRequest, Circle, CreateTreeRequest, DeletePointRequest, DeletePointsRequest, DropTreeRequest, FindWithinRangeRequest, Mutation, MutationResult, Point as ProtoPoint, QuadClient, QuadServer, Rectangle, mutation};
+    use storage::{FsyncPolicy, Point, Quad as InMemoryQuad};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Channel, Server};
+    use tonic::Code;
+
+    use crate::persistence::Persistence;
+    use crate::{QuadService, STREAM_CHUNK_SIZE, Trees, stream_entries};
+
+    fn persistence(dir: &Path) -> Persistence {
+        return Persistence {
+            snapshot_path: Some(dir.join("tree.snapshot")),
+            wal_path: Some(dir.join("tree.wal")),
+            fsync_policy: FsyncPolicy::Always
+        };
+    }
+
+    // Serves the trees persisted in `dir` on an unused local port
+    async fn serve(dir: &Path) -> (QuadClient<Channel>, Trees) {
+        return serve_with(persistence(dir)).await;
+    }
+
+    async fn serve_with(persistence: Persistence) -> (QuadClient<Channel>, Trees) {
+        let trees = persistence.open_trees(|| InMemoryQuad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, 4)).unwrap();
+
+        let service = QuadService {
+            trees: Arc::new(RwLock::new(trees)),
+            persistence: Arc::new(RwLock::new(persistence))
+        };
+        let trees = service.trees.clone();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = Server::builder()
+            .add_service(QuadServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener));
+
+        tokio::spawn(server);
+
+        let client = QuadClient::connect(format!("http://127.0.0.1:{port}")).await.unwrap();
+
+        return (client, trees);
+    }
+
+    fn create_request(name: &str) -> CreateTreeRequest {
+        return CreateTreeRequest {
+            name: name.to_string(),
+            border: Some(Rectangle {
+                top_left: Some(ProtoPoint{x: 0.0, y: 0.0}),
+                bottom_right: Some(ProtoPoint{x: 100.0, y: 100.0})
+            }),
+            capacity: 4,
+            ..Default::default()
+        };
+    }
+
+    fn add_request(tree: &str) -> AddPointRequest {
+        return point_request(tree, 50.0, 50.0);
+    }
+
+    fn point_request(tree: &str, x: f64, y: f64) -> AddPointRequest {
+        return AddPointRequest {
+            point: Some(ProtoPoint{x, y}),
+            tree: tree.to_string(),
+            ..Default::default()
+        };
+    }
+
+    fn delete_request(tree: &str, x: f64, y: f64) -> DeletePointRequest {
+        return DeletePointRequest {
+            point: Some(ProtoPoint{x, y}),
+            tree: tree.to_string()
+        };
+    }
+
+    // Every entry of the tree as (id, x, y), sorted by id
+    async fn entries(client: &mut QuadClient<Channel>, tree: &str) -> Vec<(u64, f64, f64)> {
+        let request = FindWithinRangeRequest {
+            circle: Some(Circle{x: 0.0, y: 0.0, radius: 1000.0}),
+            tree: tree.to_string()
+        };
+
+        let mut entries = client.find_within_range(request).await.unwrap().into_inner().entries
+            .into_iter()
+            .map(|e| (e.id, e.point.as_ref().unwrap().x, e.point.as_ref().unwrap().y))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|e| e.0);
+
+        return entries;
+    }
+
+    fn outcomes(results: &[MutationResult]) -> Vec<bool> {
+        return results.iter().map(|r| r.ok).collect();
+    }
+
+    async fn tree_names(client: &mut QuadClient<Channel>) -> Vec<(String, u64)> {
+        return client.list_trees(()).await.unwrap().into_inner().trees
+            .into_iter()
+            .map(|tree| (tree.name, tree.count))
+            .collect();
+    }
+
+    #[tokio::test]
+    async fn test_create_and_drop_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, trees) = serve(dir.path()).await;
+
+        client.create_tree(create_request("drivers")).await.unwrap();
+        client.add_point(add_request("drivers")).await.unwrap();
+
+        assert_eq!(client.create_tree(create_request("drivers")).await.unwrap_err().code(), Code::AlreadyExists);
+        assert_eq!(client.create_tree(create_request("two words")).await.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(client.create_tree(CreateTreeRequest{max_depth: Some(5000), ..create_request("deep")}).await.unwrap_err().code(), Code::InvalidArgument);
+
+        let mut unbounded = create_request("unbounded");
+        unbounded.border.as_mut().unwrap().bottom_right = Some(ProtoPoint{x: f64::INFINITY, y: 100.0});
+        assert_eq!(client.create_tree(unbounded).await.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(tree_names(&mut client).await, vec![(String::from("default"), 0), (String::from("drivers"), 1)]);
+
+        let snapshot = dir.path().join("tree.snapshot.tree-drivers");
+        let wal = dir.path().join("tree.wal.tree-drivers");
+
+        assert!(snapshot.exists());
+        assert!(wal.exists());
+
+        // Held the way a snapshot that started before the drop holds it
+        let dropped = trees.read().unwrap()["drivers"].clone();
+
+        client.drop_tree(DropTreeRequest{name: String::from("drivers")}).await.unwrap();
+
+        assert_eq!(tree_names(&mut client).await, vec![(String::from("default"), 0)]);
+        assert!(!snapshot.exists());
+        assert!(!wal.exists());
+
+        dropped.save_snapshot().unwrap();
+        assert!(!snapshot.exists());
+
+        assert_eq!(client.drop_tree(DropTreeRequest{name: String::from("drivers")}).await.unwrap_err().code(), Code::NotFound);
+        assert_eq!(client.drop_tree(DropTreeRequest{name: String::new()}).await.unwrap_err().code(), Code::FailedPrecondition);
+        assert_eq!(client.add_point(add_request("drivers")).await.unwrap_err().code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_named_trees_need_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, _) = serve_with(Persistence{snapshot_path: None, ..persistence(dir.path())}).await;
+
+        assert_eq!(client.create_tree(create_request("drivers")).await.unwrap_err().code(), Code::FailedPrecondition);
+        assert_eq!(tree_names(&mut client).await, vec![(String::from("default"), 0)]);
+
+        // Nothing is persisted at all, so named trees are as lasting as the default one
+        let (mut client, _) = serve_with(Persistence{snapshot_path: None, wal_path: None, fsync_policy: FsyncPolicy::Always}).await;
+
+        client.create_tree(create_request("drivers")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_named_trees_are_restored() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let (mut client, _) = serve(dir.path()).await;
+
+            client.create_tree(create_request("drivers")).await.unwrap();
+            client.add_point(add_request("drivers")).await.unwrap();
+        }
+
+        let (mut client, _) = serve(dir.path()).await;
+
+        assert_eq!(tree_names(&mut client).await, vec![(String::from("default"), 0), (String::from("drivers"), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_inserts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, _) = serve(dir.path()).await;
+
+        let status = client.add_point(point_request("", 20.0, 5.0)).await.unwrap_err();
+        assert_eq!(status.code(), Code::OutOfRange);
+        assert_eq!(status.message(), "Point (20, 5) is outside the tree bounds (0, 0) to (10, 10)");
+
+        client.create_tree(CreateTreeRequest {
+            duplicates: String::from("reject"),
+            ..create_request("unique")
+        }).await.unwrap();
+
+        let id = client.add_point(point_request("unique", 50.0, 50.0)).await.unwrap().into_inner().id;
+
+        let status = client.add_point(point_request("unique", 50.0, 50.0)).await.unwrap_err();
+        assert_eq!(status.code(), Code::AlreadyExists);
+        assert_eq!(status.message(), format!("Point (50, 50) is already occupied by entry {id}"));
+    }
+
+    #[tokio::test]
+    async fn test_deleted_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, _) = serve(dir.path()).await;
+
+        client.add_point(point_request("", 5.0, 5.0)).await.unwrap();
+        client.add_point(point_request("", 5.0, 5.0)).await.unwrap();
+        client.add_point(point_request("", 2.0, 2.0)).await.unwrap();
+
+        assert_eq!(client.delete_point(delete_request("", 5.0, 5.0)).await.unwrap().into_inner().deleted, 2);
+        assert_eq!(client.delete_point(delete_request("", 5.0, 5.0)).await.unwrap().into_inner().deleted, 0);
+        assert_eq!(client.delete_point(delete_request("", 20.0, 5.0)).await.unwrap_err().code(), Code::OutOfRange);
+        assert_eq!(tree_names(&mut client).await, vec![(String::from("default"), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_mutation_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, _) = serve(dir.path()).await;
+
+        client.create_tree(create_request("drivers")).await.unwrap();
+
+        let results = client.add_points(AddPointsRequest {
+            points: vec![
+                point_request("", 1.0, 1.0),
+                point_request("", 20.0, 20.0),
+                point_request("", 2.0, 2.0),
+                point_request("drivers", 3.0, 3.0)
+            ],
+            tree: String::new()
+        }).await.unwrap().into_inner().results;
+
+        assert_eq!(outcomes(&results), vec![true, false, true, false]);
+        assert_eq!(results[0].id, 0);
+        assert_eq!(results[1].error, "Point (20, 20) is outside the tree bounds (0, 0) to (10, 10)");
+        assert_eq!(results[2].id, 1);
+        assert_eq!(results[3].error, "Mutation targets a different tree than its batch");
+
+        let results = client.delete_points(DeletePointsRequest {
+            points: vec![
+                delete_request("", 1.0, 1.0),
+                delete_request("", 30.0, 30.0),
+                delete_request("", 3.0, 3.0)
+            ],
+            tree: String::new()
+        }).await.unwrap().into_inner().results;
+
+        assert_eq!(outcomes(&results), vec![true, false, true]);
+        assert_eq!(results.iter().map(|r| r.deleted).collect::<Vec<u64>>(), vec![1, 0, 0]);
+
+        // The first mutation of a stream picks its tree; later ones may not switch
+        let stream = tokio_stream::iter(vec![
+            Mutation{kind: Some(mutation::Kind::Add(point_request("", 4.0, 4.0)))},
+            Mutation{kind: Some(mutation::Kind::Add(point_request("drivers", 5.0, 5.0)))},
+            Mutation{kind: Some(mutation::Kind::Delete(delete_request("", 2.0, 2.0)))},
+            Mutation{kind: None}
+        ]);
+
+        let results = client.stream_mutations(stream).await.unwrap().into_inner().results;
+
+        assert_eq!(outcomes(&results), vec![true, false, true, false]);
+        assert_eq!(results[1].error, "Mutation targets a different tree than its batch");
+        assert_eq!(results[2].deleted, 1);
+
+        let applied = entries(&mut client, "").await;
+
+        assert_eq!(applied, vec![(2, 4.0, 4.0)]);
+        assert!(entries(&mut client, "drivers").await.is_empty());
+
+        // Replaying the write-ahead log rebuilds exactly what was applied
+        let (mut restarted, _) = serve(dir.path()).await;
+
+        assert_eq!(entries(&mut restarted, "").await, applied);
+        assert!(entries(&mut restarted, "drivers").await.is_empty());
+    }
+
+    // Fills the tree with a 50 by 50 grid of points
+    async fn add_grid(client: &mut QuadClient<Channel>, tree: &str) {
+        let points = (0..2500)
+            .map(|i| point_request(tree, (i % 50) as f64, (i / 50) as f64))
+            .collect::<Vec<AddPointRequest>>();
+
+        let results = client.add_points(AddPointsRequest{points, tree: tree.to_string()}).await.unwrap().into_inner().results;
+
+        assert!(results.iter().all(|r| r.ok));
+    }
+
+    #[tokio::test]
+    async fn test_stream_within_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, _) = serve(dir.path()).await;
+
+        client.create_tree(create_request("drivers")).await.unwrap();
+        add_grid(&mut client, "drivers").await;
+
+        let request = FindWithinRangeRequest {
+            circle: Some(Circle{x: 0.0, y: 0.0, radius: 1000.0}),
+            tree: String::from("drivers")
+        };
+
+        let mut stream = client.stream_within_range(request).await.unwrap().into_inner();
+        let mut sizes = Vec::new();
+        let mut ids = Vec::new();
+
+        while let Some(response) = stream.message().await.unwrap() {
+            assert_eq!(response.points.len(), response.entries.len());
+
+            sizes.push(response.entries.len());
+            ids.extend(response.entries.iter().map(|e| e.id));
+        }
+
+        ids.sort();
+
+        assert_eq!(sizes, vec![STREAM_CHUNK_SIZE, STREAM_CHUNK_SIZE, 500]);
+        assert_eq!(ids, (0..2500).collect::<Vec<u64>>());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stalled_stream_releases_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut client, trees) = serve(dir.path()).await;
+
+        client.create_tree(create_request("drivers")).await.unwrap();
+        add_grid(&mut client, "drivers").await;
+
+        let tree = trees.read().unwrap()["drivers"].clone();
+        let circle = storage::Circle{center: Point{x: 0.0, y: 0.0}, radius: 1000.0};
+
+        // Room for one chunk, which is never read, so the second send waits
+        let (sender, receiver) = mpsc::channel(1);
+        let probe = sender.clone();
+        let stream = tokio::task::spawn_blocking({
+            let tree = tree.clone();
+            move || stream_entries(&tree, &circle, &sender)
+        });
+
+        while probe.capacity() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // Writers go ahead while the stream waits on the client
+        let inserted = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let Ok(mut quad) = tree.quad.try_write() {
+                    return quad.insert(&Point{x: 99.0, y: 99.0}, Vec::new());
+                }
+                tokio::task::yield_now().await;
+            }
+        });
+
+        assert!(inserted.await.unwrap().is_ok());
+
+        // A client that goes away ends the stream without an error
+        drop(receiver);
+
+        assert!(stream.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use storage::{FsyncPolicy, Quad as InMemoryQuad};
+
+use crate::tree::{Tree, DEFAULT_TREE};
+use crate::Payload;
+
+// Separates a named tree's files from the default tree's temporary and
+// retired files, which add `.tmp` and `.old` to the configured paths
+const NAMED_TREE_INFIX: &str = ".tree-";
+
+/// Where trees are persisted. The default tree uses the configured paths as
+/// they are; every other tree adds its name after `.tree-`, e.g.
+/// `tree.snapshot.tree-drivers`. Named trees are only persisted when snapshots
+/// are enabled, since their bounds and settings live in the snapshot.
+#[derive(Clone, Debug, Default)]
+pub struct Persistence {
+    pub snapshot_path: Option<PathBuf>,
+    pub wal_path: Option<PathBuf>,
+    pub fsync_policy: FsyncPolicy
+}
+
+fn with_tree_name(path: &Path, name: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(NAMED_TREE_INFIX);
+    path.push(name);
+
+    return PathBuf::from(path);
+}
+
+impl Persistence {
+    pub fn snapshot_path(&self, name: &str) -> Option<PathBuf> {
+        let path = self.snapshot_path.as_ref()?;
+
+        if name == DEFAULT_TREE {
+            return Some(path.clone());
+        }
+
+        return Some(with_tree_name(path, name));
+    }
+
+    pub fn wal_path(&self, name: &str) -> Option<PathBuf> {
+        let path = self.wal_path.as_ref()?;
+
+        if name == DEFAULT_TREE {
+            return Some(path.clone());
+        }
+
+        self.snapshot_path.as_ref()?;
+
+        return Some(with_tree_name(path, name));
+    }
+
+    /// Names of the named trees with a snapshot on disk
+    pub fn saved_trees(&self) -> io::Result<Vec<String>> {
+        let path = match &self.snapshot_path {
+            Some(p) => p,
+            None => return Ok(vec![])
+        };
+
+        let prefix = match path.file_name() {
+            Some(f) => format!("{}{}", f.to_string_lossy(), NAMED_TREE_INFIX),
+            None => return Ok(vec![])
+        };
+
+        let directory = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new(".")
+        };
+
+        let mut names = vec![];
+
+        for entry in fs::read_dir(directory)? {
+            let file_name = entry?.file_name().to_string_lossy().into_owned();
+
+            if let Some(name) = file_name.strip_prefix(&prefix) {
+                // A named tree's temporary and retired files end in a second
+                // suffix, which is not a valid tree name
+                if Tree::is_valid_name(name) && name != DEFAULT_TREE {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names.sort();
+
+        return Ok(names);
+    }
+
+    /// Restores the default tree, built by `fresh` when it was never saved,
+    /// and every named tree with a snapshot on disk
+    pub fn open_trees<F: FnOnce() -> InMemoryQuad<Payload>>(&self, fresh: F) -> Result<HashMap<String, Arc<Tree>>, Box<dyn std::error::Error>> {
+        let mut trees = HashMap::new();
+        trees.insert(DEFAULT_TREE.to_string(), Arc::new(Tree::open(DEFAULT_TREE, self, fresh)?));
+
+        for name in self.saved_trees()? {
+            let tree = Tree::open(&name, self, InMemoryQuad::new)?;
+            trees.insert(name, Arc::new(tree));
+        }
+
+        return Ok(trees);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use storage::{FsyncPolicy, Point, Quad as InMemoryQuad, WalRecord};
+
+    use crate::tree::{Tree, DEFAULT_TREE};
+    use crate::Payload;
+    use super::Persistence;
+
+    fn persistence(dir: &Path) -> Persistence {
+        return Persistence {
+            snapshot_path: Some(dir.join("tree.snapshot")),
+            wal_path: Some(dir.join("tree.wal")),
+            fsync_policy: FsyncPolicy::Always
+        };
+    }
+
+    fn fresh() -> InMemoryQuad<Payload> {
+        return InMemoryQuad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, 4);
+    }
+
+    fn insert(tree: &Tree, x: f64) {
+        let point = Point{x, y: x};
+
+        tree.log(|| vec![WalRecord::Insert{id: None, point, value: vec![]}]).unwrap();
+        tree.quad.write().unwrap().insert(&point, vec![]).unwrap();
+    }
+
+    fn len(tree: &Tree) -> usize {
+        return tree.quad.read().unwrap().len();
+    }
+
+    #[test]
+    fn test_paths() {
+        let persistence = persistence(Path::new("data"));
+
+        assert_eq!(persistence.snapshot_path(DEFAULT_TREE).unwrap(), Path::new("data/tree.snapshot"));
+        assert_eq!(persistence.wal_path(DEFAULT_TREE).unwrap(), Path::new("data/tree.wal"));
+        assert_eq!(persistence.snapshot_path("drivers").unwrap(), Path::new("data/tree.snapshot.tree-drivers"));
+        assert_eq!(persistence.wal_path("drivers").unwrap(), Path::new("data/tree.wal.tree-drivers"));
+
+        // Without snapshots a named tree could not be restored, so it has no log either
+        let wal_only = Persistence {
+            snapshot_path: None,
+            ..persistence
+        };
+
+        assert!(wal_only.snapshot_path("drivers").is_none());
+        assert!(wal_only.wal_path("drivers").is_none());
+        assert!(wal_only.wal_path(DEFAULT_TREE).is_some());
+    }
+
+    #[test]
+    fn test_trees_named_like_temporary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = persistence(dir.path());
+
+        let trees = persistence.open_trees(fresh).unwrap();
+        insert(&trees[DEFAULT_TREE], 1.0);
+
+        for name in ["tmp", "old"] {
+            let tree = Tree::create(name, &persistence, fresh()).unwrap();
+            insert(&tree, 2.0);
+            insert(&tree, 3.0);
+        }
+
+        drop(trees);
+
+        // A crash while snapshotting the default tree leaves its temporary file behind
+        fs::write(dir.path().join("tree.snapshot.tmp"), b"partial").unwrap();
+
+        assert_eq!(persistence.saved_trees().unwrap(), vec!["old", "tmp"]);
+
+        let trees = persistence.open_trees(fresh).unwrap();
+
+        assert_eq!(trees.len(), 3);
+        assert_eq!(len(&trees[DEFAULT_TREE]), 1);
+        assert_eq!(len(&trees["tmp"]), 2);
+        assert_eq!(len(&trees["old"]), 2);
+    }
+}
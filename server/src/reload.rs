@@ -0,0 +1,429 @@
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use config::{Config, ConfigParsingError, DEFAULT_CONFIG_FILE};
+use storage::DuplicatePolicy;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+use crate::persistence::Persistence;
+use crate::tree::DEFAULT_TREE;
+use crate::{fsync_policy, Trees};
+
+/// How often the config file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Settings that can be changed on a running server. The tree settings only
+/// apply to the default tree, since named trees are created with their own.
+const LIVE_KEYS: [&str; 7] = [
+    "capacity",
+    "max_depth",
+    "duplicates",
+    "growable",
+    "snapshot_interval",
+    "wal_fsync",
+    "wal_fsync_interval"
+];
+
+/// Live settings of the default tree itself. Without snapshots the tree is
+/// rebuilt from the write-ahead log on restart, whose earlier records would
+/// then be replayed under the new settings, so these need a restart instead.
+const TREE_KEYS: [&str; 4] = [
+    "capacity",
+    "max_depth",
+    "duplicates",
+    "growable"
+];
+
+/// Settings the default tree keeps from its snapshot, once it has one, over
+/// the configuration
+const SNAPSHOT_KEYS: [&str; 4] = [
+    "top_left_x",
+    "top_left_y",
+    "bottom_right_x",
+    "bottom_right_y"
+];
+
+/// What a reload did with each key that changed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub applied: Vec<&'static str>,
+    pub restart: Vec<&'static str>,
+    /// Changes the default tree will not pick up, even after a restart,
+    /// since it is restored from its snapshot
+    pub ignored: Vec<&'static str>
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    return fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+}
+
+/// Re-reads the configuration on SIGHUP or when the config file changes,
+/// applying what can be changed live and reporting what needs a restart
+pub struct Reloader {
+    file: Option<String>,
+    overrides: Vec<(String, String)>,
+    // The settings the server is running with. Keys that need a restart keep
+    // their startup value, so they are reported until the server restarts.
+    running: Config,
+    modified: Option<SystemTime>,
+    trees: Trees,
+    persistence: Arc<RwLock<Persistence>>,
+    snapshot_interval: watch::Sender<i64>
+}
+
+impl Reloader {
+    pub fn new(file: Option<String>, overrides: Vec<(String, String)>, running: Config, trees: Trees, persistence: Arc<RwLock<Persistence>>, snapshot_interval: watch::Sender<i64>) -> Reloader {
+        let modified = modified_time(file.as_deref().unwrap_or(DEFAULT_CONFIG_FILE));
+
+        return Reloader {
+            file,
+            overrides,
+            running,
+            modified,
+            trees,
+            persistence,
+            snapshot_interval
+        };
+    }
+
+    fn path(&self) -> String {
+        return self.file.clone().unwrap_or(DEFAULT_CONFIG_FILE.to_string());
+    }
+
+    pub async fn run(mut self) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                println!("Error listening for SIGHUP {}", e);
+                return;
+            }
+        };
+
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    println!("Received SIGHUP, reloading configuration");
+                    let _ = self.reload();
+                }
+                _ = poll.tick() => {
+                    let modified = modified_time(&self.path());
+
+                    if modified != self.modified {
+                        self.modified = modified;
+
+                        println!("Config file {} changed, reloading configuration", self.path());
+                        let _ = self.reload();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn reload(&mut self) -> Result<Changes, ConfigParsingError> {
+        let config = match Config::load(self.file.as_deref(), &self.overrides) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Keeping the current configuration, reload failed: {}", e);
+                return Err(e);
+            }
+        };
+
+        let restored = self.has_snapshot();
+        let replayed = self.replays_log();
+        let mut changes = Changes::default();
+
+        for key in Config::keys() {
+            let old = self.running.get(key).unwrap_or_default();
+            let new = config.get(key).unwrap_or_default();
+
+            if old == new {
+                continue;
+            }
+
+            let change = format!("{}: {} -> {} ({})", key, old, new, config.source(key));
+
+            if LIVE_KEYS.contains(&key) && !(replayed && TREE_KEYS.contains(&key)) {
+                // The value passed validation when it was loaded
+                let _ = self.running.set(key, &new, config.source(key));
+                self.apply(key);
+
+                println!("  Applied {}", change);
+                changes.applied.push(key);
+            }
+            else if restored && SNAPSHOT_KEYS.contains(&key) {
+                println!("  Ignored {}, the default tree keeps the bounds saved in its snapshot", change);
+                changes.ignored.push(key);
+            }
+            else {
+                println!("  Requires a restart {}", change);
+                changes.restart.push(key);
+            }
+        }
+
+        if changes == Changes::default() {
+            println!("Configuration unchanged");
+        }
+
+        return Ok(changes);
+    }
+
+    fn has_snapshot(&self) -> bool {
+        match self.persistence.read() {
+            Ok(value) => return value.snapshot_path(DEFAULT_TREE).is_some_and(|path| path.exists()),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return false;
+            }
+        }
+    }
+
+    // Whether the default tree is restored by replaying its whole log, which
+    // is when there is a log but no snapshots
+    fn replays_log(&self) -> bool {
+        match self.persistence.read() {
+            Ok(value) => return value.snapshot_path.is_none() && value.wal_path.is_some(),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return true;
+            }
+        }
+    }
+
+    fn apply(&self, key: &str) {
+        match key {
+            "capacity" | "max_depth" | "duplicates" | "growable" => self.apply_to_default_tree(key),
+            "snapshot_interval" => {
+                self.snapshot_interval.send_replace(self.running.snapshot_interval);
+            },
+            "wal_fsync" | "wal_fsync_interval" => self.apply_fsync_policy(),
+            _ => {}
+        }
+    }
+
+    fn apply_to_default_tree(&self, key: &str) {
+        let tree = match self.trees.read() {
+            Ok(value) => value.get(DEFAULT_TREE).cloned(),
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+                return;
+            }
+        };
+
+        let tree = match tree {
+            Some(tree) => tree,
+            None => return
+        };
+
+        // The checkpoint keeps a restart from replaying earlier writes under
+        // the new settings, and restores the tree with them
+        let result = tree.update_settings(|value| {
+            match key {
+                "capacity" => value.set_capacity(self.running.capacity),
+                "max_depth" => value.set_max_depth(self.running.max_depth),
+                "duplicates" => {
+                    if let Ok(policy) = self.running.duplicates.parse::<DuplicatePolicy>() {
+                        value.set_duplicate_policy(policy);
+                    }
+                },
+                "growable" => value.set_growable(self.running.growable),
+                _ => {}
+            }
+        });
+
+        if let Err(e) = result {
+            println!("Error writing snapshot {}", e);
+        }
+    }
+
+    fn apply_fsync_policy(&self) {
+        let policy = match fsync_policy(&self.running) {
+            Ok(policy) => policy,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+
+        match self.persistence.write() {
+            Ok(mut value) => value.fsync_policy = policy,
+            Err(e) => {
+                println!("Error acquiring write lock {}", e);
+            }
+        }
+
+        match self.trees.read() {
+            Ok(value) => {
+                for tree in value.values() {
+                    tree.set_fsync_policy(policy);
+                }
+            },
+            Err(e) => {
+                println!("Error acquiring read lock {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, RwLock};
+
+    use config::Config;
+    use storage::{FsyncPolicy, Point, Quad as InMemoryQuad, WalRecord};
+    use tokio::sync::watch;
+
+    use crate::persistence::Persistence;
+    use crate::tree::DEFAULT_TREE;
+    use crate::{Payload, Trees};
+    use super::{Changes, Reloader};
+
+    fn write_config(dir: &Path, quadtree: &str) -> String {
+        let path = dir.join("config.ini");
+        let contents = format!(
+            "[quadtree]\n{}\n[persistence]\nsnapshot_path = {}\nwal_path = {}\n",
+            quadtree,
+            dir.join("tree.snapshot").display(),
+            dir.join("tree.wal").display()
+        );
+
+        fs::write(&path, contents).unwrap();
+
+        return path.to_string_lossy().to_string();
+    }
+
+    fn persistence(config: &Config) -> Persistence {
+        return Persistence {
+            snapshot_path: config.snapshot_path.as_ref().map(Into::into),
+            wal_path: config.wal_path.as_ref().map(Into::into),
+            fsync_policy: FsyncPolicy::Always
+        };
+    }
+
+    fn open_trees(config: &Config) -> Trees {
+        let trees = persistence(config).open_trees(|| {
+            InMemoryQuad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, config.capacity)
+                .with_growable(config.growable)
+        }).unwrap();
+
+        return Arc::new(RwLock::new(trees));
+    }
+
+    fn reloader(file: &str, trees: &Trees) -> (Reloader, watch::Receiver<i64>) {
+        let config = Config::load(Some(file), &[]).unwrap();
+        let (sender, receiver) = watch::channel(config.snapshot_interval);
+        let persistence = Arc::new(RwLock::new(persistence(&config)));
+
+        return (Reloader::new(Some(file.to_string()), vec![], config, trees.clone(), persistence, sender), receiver);
+    }
+
+    fn default_quad<T, F: FnOnce(&InMemoryQuad<Payload>) -> T>(trees: &Trees, read: F) -> T {
+        let tree = trees.read().unwrap()[DEFAULT_TREE].clone();
+        let value = tree.quad.read().unwrap();
+
+        return read(&value);
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_config(dir.path(), "capacity = 4");
+        let trees = open_trees(&Config::load(Some(&file), &[]).unwrap());
+        let (mut reloader, snapshot_interval) = reloader(&file, &trees);
+
+        assert_eq!(reloader.reload().unwrap(), Changes::default());
+
+        write_config(dir.path(), "capacity = 8\ngrowable = true\ntop_left_x = -10");
+
+        assert_eq!(reloader.reload().unwrap(), Changes {
+            applied: vec!["capacity", "growable"],
+            restart: vec!["top_left_x"],
+            ignored: vec![]
+        });
+        assert_eq!(default_quad(&trees, |quad| (quad.capacity(), quad.growable())), (8, true));
+
+        // Applying the tree settings snapshotted the tree, whose bounds now
+        // win over the configuration
+        assert!(dir.path().join("tree.snapshot").exists());
+
+        write_config(dir.path(), "capacity = 8\ngrowable = true\ntop_left_x = -10\n[persistence]\nsnapshot_interval = 10");
+
+        assert_eq!(reloader.reload().unwrap(), Changes {
+            applied: vec!["snapshot_interval"],
+            restart: vec![],
+            ignored: vec!["top_left_x"]
+        });
+        assert_eq!(*snapshot_interval.borrow(), 10);
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_config(dir.path(), "capacity = 4");
+        let trees = open_trees(&Config::load(Some(&file), &[]).unwrap());
+        let (mut reloader, _) = reloader(&file, &trees);
+
+        write_config(dir.path(), "capacity = 8\nmax_depth = deep");
+
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.running.capacity, 4);
+        assert_eq!(default_quad(&trees, |quad| quad.capacity()), 4);
+    }
+
+    #[test]
+    fn test_reloaded_settings_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_config(dir.path(), "growable = false");
+        let config = Config::load(Some(&file), &[]).unwrap();
+        let trees = open_trees(&config);
+        let (mut reloader, _) = reloader(&file, &trees);
+
+        write_config(dir.path(), "growable = true");
+        reloader.reload().unwrap();
+
+        // Accepted only because the tree may now grow
+        let point = Point{x: 50.0, y: 50.0};
+        let tree = trees.read().unwrap()[DEFAULT_TREE].clone();
+
+        tree.log(|| vec![WalRecord::Insert{id: None, point, value: vec![]}]).unwrap();
+        tree.quad.write().unwrap().insert(&point, vec![]).unwrap();
+
+        drop(tree);
+        drop(reloader);
+        drop(trees);
+
+        // Restarting with the original configuration
+        let trees = open_trees(&config);
+
+        assert!(default_quad(&trees, |quad| quad.search(&point).is_some()));
+    }
+
+    #[test]
+    fn test_tree_settings_need_restart_without_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.ini").to_string_lossy().to_string();
+        let write = |quadtree: &str| {
+            let contents = format!("[quadtree]\n{}\n[persistence]\nwal_path = {}\n", quadtree, dir.path().join("tree.wal").display());
+            fs::write(&file, contents).unwrap();
+        };
+
+        write("capacity = 4");
+
+        let trees = open_trees(&Config::load(Some(&file), &[]).unwrap());
+        let (mut reloader, _) = reloader(&file, &trees);
+
+        write("capacity = 8\nmax_depth = 4\nduplicates = reject\ngrowable = true\n[persistence]\nwal_fsync = never");
+
+        assert_eq!(reloader.reload().unwrap(), Changes {
+            applied: vec!["wal_fsync"],
+            restart: vec!["capacity", "max_depth", "duplicates", "growable"],
+            ignored: vec![]
+        });
+        assert_eq!(default_quad(&trees, |quad| (quad.capacity(), quad.growable())), (4, false));
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+
+use config::Config;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+fn read_pem(path: &str, description: &str) -> Result<Vec<u8>, String> {
+    return fs::read(path).map_err(|e| format!("Cannot read {} {}: {}", description, path, e));
+}
+
+/// Builds the TLS settings from the `[tls]` section, or None to serve
+/// plaintext. With a client CA, connections without a client certificate
+/// signed by that CA are refused.
+pub fn server_tls_config(config: &Config) -> Result<Option<ServerTlsConfig>, String> {
+    let (cert, key) = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => return Ok(None)
+    };
+
+    let identity = Identity::from_pem(read_pem(cert, "TLS certificate")?, read_pem(key, "TLS key")?);
+    let mut tls = ServerTlsConfig::new().identity(identity);
+
+    if let Some(client_ca) = &config.tls_client_ca {
+        tls = tls.client_ca_root(Certificate::from_pem(read_pem(client_ca, "client CA certificate")?));
+    }
+
+    return Ok(Some(tls));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use config::Config;
+    use proto::{QuadClient, QuadServer};
+    use rcgen::{BasicConstraints, Certificate as RcgenCertificate, CertificateParams, DnType, IsCa};
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity, Server};
+
+    use crate::QuadService;
+    use super::server_tls_config;
+
+    struct Pems {
+        ca: String,
+        server_cert: String,
+        server_key: String,
+        client_cert: String,
+        client_key: String
+    }
+
+    // A CA that signs both the server certificate, valid for localhost, and
+    // the client certificate
+    fn generate_pems() -> Pems {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, "Quad test CA");
+        let ca = RcgenCertificate::from_params(params).unwrap();
+
+        let server = RcgenCertificate::from_params(CertificateParams::new(vec![String::from("localhost")])).unwrap();
+        let client = RcgenCertificate::from_params(CertificateParams::new(vec![String::from("client")])).unwrap();
+
+        return Pems {
+            ca: ca.serialize_pem().unwrap(),
+            server_cert: server.serialize_pem_with_signer(&ca).unwrap(),
+            server_key: server.serialize_private_key_pem(),
+            client_cert: client.serialize_pem_with_signer(&ca).unwrap(),
+            client_key: client.serialize_private_key_pem()
+        };
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str) -> Option<String> {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        return Some(path.to_string_lossy().to_string());
+    }
+
+    // Serves on an unused local port and returns that port
+    async fn serve(config: &Config) -> u16 {
+        let tls = server_tls_config(config).unwrap().unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = Server::builder()
+            .tls_config(tls)
+            .unwrap()
+            .add_service(QuadServer::new(QuadService::default()))
+            .serve_with_incoming(TcpListenerStream::new(listener));
+
+        tokio::spawn(server);
+
+        return port;
+    }
+
+    async fn list_trees(port: u16, tls: Option<ClientTlsConfig>) -> bool {
+        let mut endpoint = Channel::from_shared(format!("http://127.0.0.1:{port}")).unwrap();
+
+        if let Some(tls) = tls {
+            endpoint = endpoint.tls_config(tls).unwrap();
+        }
+
+        let channel = match endpoint.connect().await {
+            Ok(channel) => channel,
+            Err(_) => return false
+        };
+
+        return QuadClient::new(channel).list_trees(()).await.is_ok();
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let dir = tempfile::tempdir().unwrap();
+        let pems = generate_pems();
+
+        let mut config = Config::default();
+        config.tls_cert = write(dir.path(), "server.pem", &pems.server_cert);
+        config.tls_key = write(dir.path(), "server.key", &pems.server_key);
+        config.tls_client_ca = write(dir.path(), "ca.pem", &pems.ca);
+
+        let port = serve(&config).await;
+
+        let server_only = ClientTlsConfig::new()
+            .domain_name("localhost")
+            .ca_certificate(Certificate::from_pem(&pems.ca));
+        let with_identity = server_only.clone()
+            .identity(Identity::from_pem(&pems.client_cert, &pems.client_key));
+
+        assert!(list_trees(port, Some(with_identity)).await);
+        assert!(!list_trees(port, Some(server_only)).await);
+        assert!(!list_trees(port, None).await);
+    }
+
+    #[tokio::test]
+    async fn test_tls_without_client_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let pems = generate_pems();
+
+        let mut config = Config::default();
+        config.tls_cert = write(dir.path(), "server.pem", &pems.server_cert);
+        config.tls_key = write(dir.path(), "server.key", &pems.server_key);
+
+        let port = serve(&config).await;
+
+        let tls = ClientTlsConfig::new()
+            .domain_name("localhost")
+            .ca_certificate(Certificate::from_pem(&pems.ca));
+
+        assert!(list_trees(port, Some(tls)).await);
+
+        // The server must present a certificate signed by a CA the client trusts
+        let other = generate_pems();
+        let untrusted = ClientTlsConfig::new()
+            .domain_name("localhost")
+            .ca_certificate(Certificate::from_pem(&other.ca));
+
+        assert!(!list_trees(port, Some(untrusted)).await);
+    }
+
+    #[test]
+    fn test_missing_files() {
+        assert!(server_tls_config(&Config::default()).unwrap().is_none());
+
+        let mut config = Config::default();
+        config.tls_cert = Some(String::from("/nonexistent/server.pem"));
+        config.tls_key = Some(String::from("/nonexistent/server.key"));
+
+        assert!(server_tls_config(&config).err().unwrap().contains("/nonexistent/server.pem"));
+    }
+}
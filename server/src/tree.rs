@@ -0,0 +1,277 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use storage::{FsyncPolicy, Quad as InMemoryQuad, WalRecord, WriteAheadLog};
+use tonic::Status;
+
+use crate::persistence::Persistence;
+use crate::Payload;
+
+/// Name of the tree targeted by requests that leave `tree` empty
+pub const DEFAULT_TREE: &str = "default";
+
+/// A named quad tree along with the files that persist it
+#[derive(Debug)]
+pub struct Tree {
+    pub name: String,
+    pub quad: RwLock<InMemoryQuad<Payload>>,
+    wal: Option<Mutex<WriteAheadLog>>,
+    persistence: Persistence,
+    // Set under the write lock once the tree's files are deleted, so a
+    // snapshot that was already under way does not write them back
+    dropped: AtomicBool
+}
+
+// Returns None when no snapshot has been written yet
+fn load_snapshot(path: &Path) -> Result<Option<InMemoryQuad<Payload>>, Box<dyn std::error::Error>> {
+    match File::open(path) {
+        Ok(file) => {
+            let quad = InMemoryQuad::load_from(&mut BufReader::new(file))?;
+            return Ok(Some(quad));
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(e.into());
+        }
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => return Ok(())
+    }
+}
+
+impl Tree {
+    pub fn is_valid_name(name: &str) -> bool {
+        return !name.is_empty() && name.len() <= 64 &&
+            name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    }
+
+    /// Restores the tree from its snapshot and write-ahead log, falling back
+    /// to `fresh` when nothing has been saved
+    pub fn open<F: FnOnce() -> InMemoryQuad<Payload>>(name: &str, persistence: &Persistence, fresh: F) -> Result<Tree, Box<dyn std::error::Error>> {
+        let snapshot_path = persistence.snapshot_path(name);
+
+        let snapshot = match &snapshot_path {
+            Some(path) => load_snapshot(path)?,
+            None => None
+        };
+
+        // A restored tree keeps the bounds and settings it was saved with
+        let mut quad = match snapshot {
+            Some(quad) => {
+                println!("Restored {} points into tree {} from snapshot", quad.len(), name);
+                quad
+            }
+            None => fresh()
+        };
+
+        let wal = match persistence.wal_path(name) {
+            Some(path) => {
+                let replay = WriteAheadLog::replay(&path, snapshot_path.as_deref(), &mut quad)?;
+
+                println!("Replayed {} records into tree {} from the write-ahead log", replay.records, name);
+
+                if replay.discarded > 0 {
+                    println!("Discarded {} bytes of torn write-ahead log tail", replay.discarded);
+                }
+
+                Some(Mutex::new(WriteAheadLog::open(&path, persistence.fsync_policy)?))
+            }
+            None => None
+        };
+
+        return Ok(Tree {
+            name: name.to_string(),
+            quad: RwLock::new(quad),
+            wal,
+            persistence: persistence.clone(),
+            dropped: AtomicBool::new(false)
+        });
+    }
+
+    /// Starts a new tree, replacing any files left behind by an earlier tree
+    /// of the same name. The initial snapshot makes the tree survive a restart.
+    pub fn create(name: &str, persistence: &Persistence, quad: InMemoryQuad<Payload>) -> Result<Tree, Box<dyn std::error::Error + Send + Sync>> {
+        let mut tree = Tree {
+            name: name.to_string(),
+            quad: RwLock::new(quad),
+            wal: None,
+            persistence: persistence.clone(),
+            dropped: AtomicBool::new(false)
+        };
+
+        tree.remove_files()?;
+
+        if let Some(path) = persistence.wal_path(name) {
+            tree.wal = Some(Mutex::new(WriteAheadLog::open(&path, persistence.fsync_policy)?));
+        }
+
+        tree.save_snapshot()?;
+
+        return Ok(tree);
+    }
+
+    // Appends the mutations to the write-ahead log, if there is one, before
+    // they are applied. Callers hold the tree's write lock so records are
+    // logged in the order they are applied. When logging fails none of the
+    // records is kept, so callers must not apply any of them.
+    pub fn log<F: FnOnce() -> Vec<WalRecord<Payload>>>(&self, records: F) -> Result<(), Status> {
+        let wal = match &self.wal {
+            Some(wal) => wal,
+            None => return Ok(())
+        };
+
+        let lock = wal.lock();
+
+        match lock {
+            Ok(mut value) => {
+                let result = records()
+                    .iter()
+                    .try_for_each(|r| value.append(r))
+                    .and_then(|_| value.commit());
+
+                match result {
+                    Ok(()) => return Ok(()),
+                    Err(e) => {
+                        println!("Error writing to the write-ahead log {}", e);
+                        return Err(Status::internal("Internal Error"));
+                    }
+                }
+            },
+            Err(e) => {
+                println!("Error acquiring log lock {}", e);
+                return Err(Status::internal("Internal Error"));
+            }
+        }
+    }
+
+    pub fn set_fsync_policy(&self, policy: FsyncPolicy) {
+        if let Some(wal) = &self.wal {
+            match wal.lock() {
+                Ok(mut value) => value.set_policy(policy),
+                Err(e) => {
+                    println!("Error acquiring log lock {}", e);
+                }
+            }
+        }
+    }
+
+    // Syncs the write-ahead log if commits under an interval fsync policy
+    // have left it unsynced for longer than the interval
+    pub fn sync_log(&self) -> io::Result<()> {
+        if let Some(wal) = &self.wal {
+            match wal.lock() {
+                Ok(mut value) => return value.sync_due(),
+                Err(e) => {
+                    println!("Error acquiring log lock {}", e);
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub fn save_snapshot(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Holding the read lock keeps writers out until the snapshot is complete
+        let value = match self.quad.read() {
+            Ok(value) => value,
+            Err(e) => {
+                return Err(format!("Error acquiring read lock {}", e).into());
+            }
+        };
+
+        return self.write_snapshot(&value);
+    }
+
+    /// Changes the tree's settings and checkpoints it before writers get
+    /// back in. Records logged under the old settings are never replayed
+    /// under the new ones, which could accept or merge points differently.
+    pub fn update_settings<F: FnOnce(&mut InMemoryQuad<Payload>)>(&self, update: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut value = match self.quad.write() {
+            Ok(value) => value,
+            Err(e) => {
+                return Err(format!("Error acquiring write lock {}", e).into());
+            }
+        };
+
+        update(&mut value);
+
+        return self.write_snapshot(&value);
+    }
+
+    // With a write-ahead log the snapshot is taken as a checkpoint that also
+    // empties the log. Otherwise it is written to a temporary file first so a
+    // crash mid-write never replaces the last good snapshot. Callers hold one
+    // of the tree's locks.
+    fn write_snapshot(&self, value: &InMemoryQuad<Payload>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let path = match self.persistence.snapshot_path(&self.name) {
+            Some(p) => p,
+            None => return Ok(())
+        };
+
+        if self.dropped.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        if let Some(wal) = &self.wal {
+            match wal.lock() {
+                Ok(mut wal) => {
+                    wal.checkpoint(value, &path)?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    return Err(format!("Error acquiring log lock {}", e).into());
+                }
+            }
+        }
+
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+
+        value.save_to(&mut writer)?;
+
+        writer.flush()?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&temp_path, &path)?;
+
+        return Ok(());
+    }
+
+    /// Deletes the files of a dropped tree. The write lock waits out any
+    /// snapshot in progress, and later snapshots of the tree are skipped.
+    pub fn discard(&self) -> io::Result<()> {
+        let _lock = match self.quad.write() {
+            Ok(value) => value,
+            Err(e) => {
+                return Err(io::Error::other(format!("Error acquiring write lock {}", e)));
+            }
+        };
+
+        self.dropped.store(true, Ordering::SeqCst);
+
+        return self.remove_files();
+    }
+
+    /// Deletes the tree's snapshot and write-ahead log
+    pub fn remove_files(&self) -> io::Result<()> {
+        if let Some(path) = self.persistence.snapshot_path(&self.name) {
+            remove_if_exists(&path)?;
+        }
+
+        if let Some(path) = self.persistence.wal_path(&self.name) {
+            remove_if_exists(&path)?;
+        }
+
+        return Ok(());
+    }
+}
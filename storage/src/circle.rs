@@ -1,4 +1,4 @@
-use crate::{point::Point, rectangle::Rectangle};
+use crate::{point::Point, rectangle::Rectangle, shape::Shape};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Circle {
@@ -15,7 +15,7 @@ impl Circle {
         let h = rectangle.bottom_right.y - rectangle.top_left.y;
 
         let dx = self.center.x - f64::max(x, f64::min(self.center.x, x + w));
-        let dy = self.center.y - f64::max(y, f64::min(y, self.center.y + h));
+        let dy = self.center.y - f64::max(y, f64::min(self.center.y, y + h));
 
         return (dx * dx + dy * dy) <= self.radius * self.radius;
     }
@@ -28,3 +28,17 @@ impl Circle {
         return (point1.x - point2.x).powf(2.0) + (point1.y - point2.y).powf(2.0);
     }
 }
+
+impl Shape for Circle {
+    fn intersects(&self, rectangle: &Rectangle) -> bool {
+        return Circle::intersects(self, rectangle);
+    }
+
+    fn contains(&self, point: &Point) -> bool {
+        return Circle::contains(self, point);
+    }
+
+    fn contains_rect(&self, rectangle: &Rectangle) -> bool {
+        return rectangle.corners().iter().all(|c| self.contains(c));
+    }
+}
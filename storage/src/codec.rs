@@ -0,0 +1,87 @@
+use std::io::{self, Read, Write};
+
+/// Binary encoding used by snapshots. Implement it for a payload type to
+/// make a `Quad` of that type persistable. Numbers are little endian.
+pub trait Codec: Sized {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! impl_codec_for_number {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    return writer.write_all(&self.to_le_bytes());
+                }
+
+                fn decode<R: Read>(reader: &mut R) -> io::Result<$t> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+
+                    return Ok(<$t>::from_le_bytes(bytes));
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_number!(u8, u32, u64, i32, i64, f64);
+
+impl Codec for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        return (*self as u8).encode(writer);
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<bool> {
+        match u8::decode(reader)? {
+            0 => return Ok(false),
+            1 => return Ok(true),
+            b => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid bool byte {b}")))
+        }
+    }
+}
+
+impl Codec for () {
+    fn encode<W: Write>(&self, _writer: &mut W) -> io::Result<()> {
+        return Ok(());
+    }
+
+    fn decode<R: Read>(_reader: &mut R) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl Codec for Vec<u8> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (self.len() as u64).encode(writer)?;
+
+        return writer.write_all(self);
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+        let len = u64::decode(reader)?;
+
+        // Read through `take` rather than allocating `len` up front, so a
+        // corrupt length fails at end of input instead of exhausting memory
+        let mut bytes = vec![];
+        reader.take(len).read_to_end(&mut bytes)?;
+
+        if (bytes.len() as u64) < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "payload is truncated"));
+        }
+
+        return Ok(bytes);
+    }
+}
+
+impl Codec for String {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        return self.as_bytes().to_vec().encode(writer);
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<String> {
+        return String::from_utf8(Vec::<u8>::decode(reader)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+}
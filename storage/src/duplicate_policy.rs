@@ -0,0 +1,50 @@
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use crate::codec::Codec;
+
+/// How a `Quad` treats an insert at a point that is already occupied
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Store every entry, even if several share a location
+    #[default]
+    Allow,
+    /// Refuse the insert
+    Reject,
+    /// Keep the existing entry and increment its count
+    Count
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DuplicatePolicy, String> {
+        match s.to_lowercase().as_str() {
+            "allow" => return Ok(DuplicatePolicy::Allow),
+            "reject" => return Ok(DuplicatePolicy::Reject),
+            "count" => return Ok(DuplicatePolicy::Count),
+            _ => return Err(format!("Unknown duplicate policy: {s}"))
+        }
+    }
+}
+
+impl Codec for DuplicatePolicy {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let tag: u8 = match self {
+            DuplicatePolicy::Allow => 0,
+            DuplicatePolicy::Reject => 1,
+            DuplicatePolicy::Count => 2
+        };
+
+        return tag.encode(writer);
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<DuplicatePolicy> {
+        match u8::decode(reader)? {
+            0 => return Ok(DuplicatePolicy::Allow),
+            1 => return Ok(DuplicatePolicy::Reject),
+            2 => return Ok(DuplicatePolicy::Count),
+            t => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown duplicate policy tag {t}")))
+        }
+    }
+}
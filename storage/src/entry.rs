@@ -0,0 +1,10 @@
+use crate::point::Point;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry<V> {
+    pub id: u64,
+    pub point: Point,
+    pub value: V,
+    /// Number of inserts folded into this entry under `DuplicatePolicy::Count`
+    pub count: u64
+}
@@ -0,0 +1,14 @@
+use std::time::Duration;
+
+/// When `WriteAheadLog::commit` forces appended records to disk
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FsyncPolicy {
+    /// Every commit is fsync'd before it returns
+    #[default]
+    Always,
+    /// Commits are fsync'd at most once per interval. A crash can lose the
+    /// records committed since the last fsync.
+    Interval(Duration),
+    /// Records are handed to the operating system and never fsync'd explicitly
+    Never
+}
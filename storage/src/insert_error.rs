@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// Reason an entry was not stored by `Quad::insert`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InsertError {
+    /// The point lies outside the tree bounds
+    OutOfBounds,
+    /// An entry already exists at the point and duplicates are rejected.
+    /// Holds the id of the existing entry.
+    Duplicate(u64),
+    /// The requested id already belongs to another entry
+    IdInUse(u64),
+    /// The requested id is `u64::MAX`, which is reserved so the next id
+    /// can always be computed
+    IdReserved(u64)
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InsertError::OutOfBounds => return write!(f, "point is outside the tree bounds"),
+            InsertError::Duplicate(id) => return write!(f, "point is already occupied by entry {id}"),
+            InsertError::IdInUse(id) => return write!(f, "id {id} is already in use"),
+            InsertError::IdReserved(id) => return write!(f, "id {id} is reserved")
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
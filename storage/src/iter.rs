@@ -0,0 +1,43 @@
+use std::slice;
+
+use crate::entry::Entry;
+use crate::quad::Quad;
+
+/// Lazily yields every entry in the tree, leaf by leaf
+pub struct Iter<'a, V> {
+    stack: Vec<&'a Quad<V>>,
+    entries: slice::Iter<'a, Entry<V>>
+}
+
+impl<'a, V> Iter<'a, V> {
+    pub(crate) fn new(quad: &'a Quad<V>) -> Iter<'a, V> {
+        return Iter {
+            stack: vec![quad],
+            entries: [].iter()
+        }
+    }
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = &'a Entry<V>;
+
+    fn next(&mut self) -> Option<&'a Entry<V>> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(entry)
+            }
+
+            let quad = self.stack.pop()?;
+
+            if quad.is_leaf {
+                self.entries = quad.entries.iter();
+                continue
+            }
+
+            // Pushed in reverse so children are visited top left first
+            for child in [&quad.bottom_right_quad, &quad.bottom_left_quad, &quad.top_right_quad, &quad.top_left_quad] {
+                self.stack.push(child.as_ref().unwrap());
+            }
+        }
+    }
+}
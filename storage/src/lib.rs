@@ -1,8 +1,37 @@
 mod circle;
+mod codec;
+mod duplicate_policy;
+mod entry;
+mod fsync_policy;
+mod insert_error;
+mod iter;
+mod node;
+mod nodes;
 mod quad;
 mod point;
+mod polygon;
+mod query_iter;
 mod rectangle;
+mod shape;
+mod snapshot_error;
+mod wal_record;
+mod write_ahead_log;
 
-pub use quad::Quad;
+pub use quad::{Quad, MAX_DEPTH};
 pub use point::Point;
 pub use circle::Circle;
+pub use rectangle::Rectangle;
+pub use polygon::Polygon;
+pub use query_iter::QueryIter;
+pub use shape::Shape;
+pub use entry::Entry;
+pub use duplicate_policy::DuplicatePolicy;
+pub use insert_error::InsertError;
+pub use iter::Iter;
+pub use node::Node;
+pub use nodes::Nodes;
+pub use codec::Codec;
+pub use snapshot_error::SnapshotError;
+pub use fsync_policy::FsyncPolicy;
+pub use wal_record::WalRecord;
+pub use write_ahead_log::{Replay, WriteAheadLog};
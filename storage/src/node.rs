@@ -0,0 +1,13 @@
+use crate::entry::Entry;
+use crate::rectangle::Rectangle;
+
+/// A single node of the tree as seen by `Quad::nodes`
+#[derive(Debug)]
+pub struct Node<'a, V> {
+    // Distance from the node the walk started at, which has depth 0
+    pub depth: i64,
+    pub border: &'a Rectangle,
+    pub is_leaf: bool,
+    // Always empty for internal nodes
+    pub entries: &'a [Entry<V>]
+}
@@ -0,0 +1,37 @@
+use crate::node::Node;
+use crate::quad::Quad;
+
+/// Lazily walks every node in the tree, parents before their children
+pub struct Nodes<'a, V> {
+    stack: Vec<(&'a Quad<V>, i64)>
+}
+
+impl<'a, V> Nodes<'a, V> {
+    pub(crate) fn new(quad: &'a Quad<V>) -> Nodes<'a, V> {
+        return Nodes {
+            stack: vec![(quad, 0)]
+        }
+    }
+}
+
+impl<'a, V> Iterator for Nodes<'a, V> {
+    type Item = Node<'a, V>;
+
+    fn next(&mut self) -> Option<Node<'a, V>> {
+        let (quad, depth) = self.stack.pop()?;
+
+        if !quad.is_leaf {
+            // Pushed in reverse so children are visited top left first
+            for child in [&quad.bottom_right_quad, &quad.bottom_left_quad, &quad.top_right_quad, &quad.top_left_quad] {
+                self.stack.push((child.as_ref().unwrap(), depth + 1));
+            }
+        }
+
+        return Some(Node {
+            depth,
+            border: &quad.border,
+            is_leaf: quad.is_leaf,
+            entries: &quad.entries
+        });
+    }
+}
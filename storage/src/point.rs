@@ -4,3 +4,8 @@ pub struct Point {
     pub y: f64
 }
 
+impl Point {
+    pub fn distance(&self, point: &Point) -> f64 {
+        return ((self.x - point.x).powf(2.0) + (self.y - point.y).powf(2.0)).sqrt();
+    }
+}
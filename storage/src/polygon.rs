@@ -0,0 +1,204 @@
+use crate::{point::Point, rectangle::Rectangle, shape::Shape};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polygon {
+    pub vertices: Vec<Point>
+}
+
+impl Polygon {
+    pub fn from(vertices: Vec<Point>) -> Polygon {
+        return Polygon {
+            vertices
+        }
+    }
+
+    /// Points on an edge or vertex count as inside, as they do for circles
+    /// and rectangles
+    pub fn contains(&self, point: &Point) -> bool {
+        if self.edges().any(|(a, b)| orientation(&a, &b, point) == 0.0 && on_segment(&a, &b, point)) {
+            return true
+        }
+
+        let mut inside = false;
+
+        for (a, b) in self.edges() {
+            if (a.y > point.y) != (b.y > point.y) {
+                let crossing_x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+
+                if point.x < crossing_x {
+                    inside = !inside;
+                }
+            }
+        }
+
+        return inside;
+    }
+
+    pub fn intersects(&self, rectangle: &Rectangle) -> bool {
+        if !self.bounding_box().intersects(rectangle) {
+            return false
+        }
+
+        if self.vertices.iter().any(|v| rectangle.contains(v)) {
+            return true
+        }
+
+        if rectangle.corners().iter().any(|c| self.contains(c)) {
+            return true
+        }
+
+        return self.crosses(rectangle);
+    }
+
+    /// True when the whole rectangle lies strictly inside the polygon
+    pub fn contains_rectangle(&self, rectangle: &Rectangle) -> bool {
+        return rectangle.corners().iter().all(|c| self.contains(c)) && !self.crosses(rectangle);
+    }
+
+    fn bounding_box(&self) -> Rectangle {
+        let mut top_left = Point { x: f64::INFINITY, y: f64::INFINITY };
+        let mut bottom_right = Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY };
+
+        for vertex in &self.vertices {
+            top_left.x = f64::min(top_left.x, vertex.x);
+            top_left.y = f64::min(top_left.y, vertex.y);
+            bottom_right.x = f64::max(bottom_right.x, vertex.x);
+            bottom_right.y = f64::max(bottom_right.y, vertex.y);
+        }
+
+        return Rectangle::from(&top_left, &bottom_right);
+    }
+
+    // Whether any polygon edge touches any edge of the rectangle
+    fn crosses(&self, rectangle: &Rectangle) -> bool {
+        let corners = rectangle.corners();
+        let sides = [
+            (corners[0], corners[1]),
+            (corners[1], corners[2]),
+            (corners[2], corners[3]),
+            (corners[3], corners[0])
+        ];
+
+        return self.edges().any(|(a, b)| {
+            sides.iter().any(|(c, d)| segments_intersect(&a, &b, c, d))
+        });
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        let count = self.vertices.len();
+
+        return (0..count).map(move |i| (self.vertices[i], self.vertices[(i + 1) % count]));
+    }
+}
+
+impl Shape for Polygon {
+    fn intersects(&self, rectangle: &Rectangle) -> bool {
+        return Polygon::intersects(self, rectangle);
+    }
+
+    fn contains(&self, point: &Point) -> bool {
+        return Polygon::contains(self, point);
+    }
+
+    fn contains_rect(&self, rectangle: &Rectangle) -> bool {
+        return self.contains_rectangle(rectangle);
+    }
+}
+
+fn orientation(a: &Point, b: &Point, c: &Point) -> f64 {
+    return (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+}
+
+fn on_segment(a: &Point, b: &Point, point: &Point) -> bool {
+    return point.x >= f64::min(a.x, b.x) && point.x <= f64::max(a.x, b.x) &&
+            point.y >= f64::min(a.y, b.y) && point.y <= f64::max(a.y, b.y)
+}
+
+fn segments_intersect(a: &Point, b: &Point, c: &Point, d: &Point) -> bool {
+    let o1 = orientation(a, b, c);
+    let o2 = orientation(a, b, d);
+    let o3 = orientation(c, d, a);
+    let o4 = orientation(c, d, b);
+
+    if ((o1 > 0.0 && o2 < 0.0) || (o1 < 0.0 && o2 > 0.0)) && ((o3 > 0.0 && o4 < 0.0) || (o3 < 0.0 && o4 > 0.0)) {
+        return true
+    }
+
+    return (o1 == 0.0 && on_segment(a, b, c)) || (o2 == 0.0 && on_segment(a, b, d)) ||
+            (o3 == 0.0 && on_segment(c, d, a)) || (o4 == 0.0 && on_segment(c, d, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::point::Point;
+    use crate::rectangle::Rectangle;
+
+    use super::Polygon;
+
+    fn square() -> Polygon {
+        return Polygon::from(vec![
+            Point{x: 0.0, y: 0.0},
+            Point{x: 10.0, y: 0.0},
+            Point{x: 10.0, y: 10.0},
+            Point{x: 0.0, y: 10.0}
+        ]);
+    }
+
+    #[test]
+    fn test_contains() {
+        let polygon = square();
+
+        assert!(polygon.contains(&Point{x: 5.0, y: 5.0}));
+        assert!(!polygon.contains(&Point{x: 15.0, y: 5.0}));
+        assert!(!polygon.contains(&Point{x: 5.0, y: -1.0}));
+    }
+
+    #[test]
+    fn test_contains_boundary() {
+        let polygon = square();
+
+        // Every edge and vertex, including the right and top edges that ray
+        // casting alone leaves out
+        assert!(polygon.contains(&Point{x: 5.0, y: 0.0}));
+        assert!(polygon.contains(&Point{x: 10.0, y: 5.0}));
+        assert!(polygon.contains(&Point{x: 5.0, y: 10.0}));
+        assert!(polygon.contains(&Point{x: 0.0, y: 5.0}));
+        assert!(polygon.contains(&Point{x: 0.0, y: 0.0}));
+        assert!(polygon.contains(&Point{x: 10.0, y: 10.0}));
+
+        let triangle = Polygon::from(vec![
+            Point{x: 0.0, y: 0.0},
+            Point{x: 10.0, y: 0.0},
+            Point{x: 0.0, y: 10.0}
+        ]);
+
+        assert!(triangle.contains(&Point{x: 5.0, y: 5.0}));
+        assert!(!triangle.contains(&Point{x: 5.1, y: 5.1}));
+        // On the line through an edge but past its end
+        assert!(!triangle.contains(&Point{x: 15.0, y: -5.0}));
+    }
+
+    #[test]
+    fn test_intersects() {
+        let polygon = square();
+
+        let overlapping = Rectangle::from(&Point{x: 8.0, y: 8.0}, &Point{x: 12.0, y: 12.0});
+        let enclosing = Rectangle::from(&Point{x: -5.0, y: -5.0}, &Point{x: 15.0, y: 15.0});
+        let disjoint = Rectangle::from(&Point{x: 20.0, y: 20.0}, &Point{x: 30.0, y: 30.0});
+
+        assert!(polygon.intersects(&overlapping));
+        assert!(polygon.intersects(&enclosing));
+        assert!(!polygon.intersects(&disjoint));
+    }
+
+    #[test]
+    fn test_contains_rectangle() {
+        let polygon = square();
+
+        let inside = Rectangle::from(&Point{x: 2.0, y: 2.0}, &Point{x: 8.0, y: 8.0});
+        let overlapping = Rectangle::from(&Point{x: 8.0, y: 8.0}, &Point{x: 12.0, y: 12.0});
+
+        assert!(polygon.contains_rectangle(&inside));
+        assert!(!polygon.contains_rectangle(&overlapping));
+    }
+}
@@ -1,23 +1,62 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{Read, Write};
+
 use crate::circle::Circle;
+use crate::codec::Codec;
+use crate::duplicate_policy::DuplicatePolicy;
+use crate::entry::Entry;
+use crate::insert_error::InsertError;
+use crate::iter::Iter;
+use crate::nodes::Nodes;
 use crate::point::Point;
+use crate::polygon::Polygon;
+use crate::query_iter::QueryIter;
 use crate::rectangle::Rectangle;
+use crate::shape::Shape;
+use crate::snapshot_error::SnapshotError;
+
+const DEFAULT_MAX_DEPTH: i64 = 24;
+
+/// Deepest `max_depth` a snapshot may carry. Halving an f64 range this many
+/// times already leaves nodes narrower than the gap between adjacent values.
+pub const MAX_DEPTH: i64 = 1024;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"QUAD";
+const SNAPSHOT_VERSION: u32 = 1;
 
 #[derive(Default, Debug)]
-pub struct Quad {
+pub struct Quad<V> {
     pub border: Rectangle,
 
-    pub points: Vec<Point>,
+    // The structure is only changed through the methods below, which keep
+    // `size` and the root's `ids` in step with it
+    pub(crate) entries: Vec<Entry<V>>,
     capacity: i64,
-    pub is_leaf: bool,
-
-    pub top_left_quad: Option<Box<Quad>>,
-    pub top_right_quad: Option<Box<Quad>>,
-    pub bottom_left_quad: Option<Box<Quad>>,
-    pub bottom_right_quad: Option<Box<Quad>>
+    // Number of entries held by this node and all of its descendants
+    size: usize,
+    pub(crate) is_leaf: bool,
+
+    // Leaves at `max_depth` are no longer subdivided and may exceed `capacity`
+    pub(crate) depth: i64,
+    max_depth: i64,
+    duplicate_policy: DuplicatePolicy,
+    // When set, inserting outside the border grows the root instead of failing
+    growable: bool,
+
+    // Only maintained on the root node. Maps every entry id to its location,
+    // which is enough to walk straight down to the leaf holding the entry.
+    ids: HashMap<u64, Point>,
+    next_id: u64,
+
+    pub(crate) top_left_quad: Option<Box<Quad<V>>>,
+    pub(crate) top_right_quad: Option<Box<Quad<V>>>,
+    pub(crate) bottom_left_quad: Option<Box<Quad<V>>>,
+    pub(crate) bottom_right_quad: Option<Box<Quad<V>>>
 }
 
-impl Quad {
-    pub fn new() -> Quad {
+impl<V> Quad<V> {
+    pub fn new() -> Quad<V> {
         let top_left = Point{
             x: 0.0,
             y: 0.0
@@ -27,13 +66,26 @@ impl Quad {
             y: 10.0
         };
         
+        return Quad::from(top_left, bottom_right, 10);
+    }
+
+    pub fn from(top_left: Point, bottom_right: Point, capacity: i64) -> Quad<V> {
         return Quad {
             border: Rectangle::from(&top_left, &bottom_right),
 
-            points: vec![],
-            capacity: 10,
+            entries: vec![],
+            capacity,
+            size: 0,
             is_leaf: true,
 
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            duplicate_policy: DuplicatePolicy::Allow,
+            growable: false,
+
+            ids: HashMap::new(),
+            next_id: 0,
+
             top_left_quad: None,
             top_right_quad: None,
             bottom_left_quad: None,
@@ -41,21 +93,112 @@ impl Quad {
         }
     }
 
-    pub fn from(top_left: Point, bottom_right: Point, capacity: i64) -> Quad {
-        return Quad {
-            border: Rectangle::from(&top_left, &bottom_right),
+    pub fn with_max_depth(mut self, max_depth: i64) -> Quad<V> {
+        self.max_depth = max_depth;
+        return self;
+    }
 
-            points: vec![],
-            capacity,
-            is_leaf: true,
+    pub fn with_duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Quad<V> {
+        self.duplicate_policy = duplicate_policy;
+        return self;
+    }
 
-            top_left_quad: None,
-            top_right_quad: None,
-            bottom_left_quad: None,
-            bottom_right_quad: None
+    pub fn with_growable(mut self, growable: bool) -> Quad<V> {
+        self.growable = growable;
+        return self;
+    }
+
+    pub fn capacity(&self) -> i64 {
+        return self.capacity;
+    }
+
+    pub fn max_depth(&self) -> i64 {
+        return self.max_depth;
+    }
+
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        return self.duplicate_policy;
+    }
+
+    pub fn growable(&self) -> bool {
+        return self.growable;
+    }
+
+    /// Entries held by this node, always empty unless it is a leaf
+    pub fn entries(&self) -> &[Entry<V>] {
+        return &self.entries;
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        return self.is_leaf;
+    }
+
+    pub fn depth(&self) -> i64 {
+        return self.depth;
+    }
+
+    /// The top left, top right, bottom left and bottom right children
+    pub fn children(&self) -> [Option<&Quad<V>>; 4] {
+        return [
+            self.top_left_quad.as_deref(),
+            self.top_right_quad.as_deref(),
+            self.bottom_left_quad.as_deref(),
+            self.bottom_right_quad.as_deref()
+        ];
+    }
+
+    /// Changes the capacity of every node. Existing nodes are left as they
+    /// are; a leaf over the new capacity splits on its next insert.
+    pub fn set_capacity(&mut self, capacity: i64) {
+        self.update_settings(&|quad| quad.capacity = capacity);
+    }
+
+    /// Changes the depth at which leaves stop splitting. Nodes already deeper
+    /// than a lowered `max_depth` are kept.
+    pub fn set_max_depth(&mut self, max_depth: i64) {
+        self.update_settings(&|quad| quad.max_depth = max_depth);
+    }
+
+    /// Changes how future inserts handle existing points. Points merged or
+    /// stored under the old policy are kept.
+    pub fn set_duplicate_policy(&mut self, duplicate_policy: DuplicatePolicy) {
+        self.update_settings(&|quad| quad.duplicate_policy = duplicate_policy);
+    }
+
+    pub fn set_growable(&mut self, growable: bool) {
+        self.update_settings(&|quad| quad.growable = growable);
+    }
+
+    // Settings are copied into every node as it is created, so changing one
+    // means walking the whole tree
+    fn update_settings<F: Fn(&mut Quad<V>)>(&mut self, update: &F) {
+        update(self);
+
+        let children = [
+            &mut self.top_left_quad,
+            &mut self.top_right_quad,
+            &mut self.bottom_left_quad,
+            &mut self.bottom_right_quad
+        ];
+
+        for child in children.into_iter().flatten() {
+            child.update_settings(update);
         }
     }
 
+    fn child(&self, top_left: Point, bottom_right: Point) -> Quad<V> {
+        return Quad::from(top_left, bottom_right, self.capacity)
+            .with_max_depth(self.max_depth)
+            .with_duplicate_policy(self.duplicate_policy)
+            .with_growable(self.growable)
+            .at_depth(self.depth + 1);
+    }
+
+    fn at_depth(mut self, depth: i64) -> Quad<V> {
+        self.depth = depth;
+        return self;
+    }
+
     fn subdivide(&mut self) {
         let top_left_corner_1 = Point{
             x: self.border.top_left.x,
@@ -67,7 +210,7 @@ impl Quad {
             y: (self.border.top_left.y + self.border.bottom_right.y) / 2.0
         };
 
-        let new_quad = Quad::from(top_left_corner_1, top_left_corner_2, self.capacity);
+        let new_quad = self.child(top_left_corner_1, top_left_corner_2);
         self.top_left_quad = Some(Box::new(new_quad));
 
         let bottom_left_corner_1 = Point{
@@ -80,7 +223,7 @@ impl Quad {
             y: self.border.bottom_right.y
         };
 
-        let new_quad = Quad::from(bottom_left_corner_1, bottom_left_corner_2, self.capacity);
+        let new_quad = self.child(bottom_left_corner_1, bottom_left_corner_2);
         self.bottom_left_quad = Some(Box::new(new_quad));
 
         let top_right_corner_1 = Point{
@@ -93,7 +236,7 @@ impl Quad {
             y: (self.border.top_left.y + self.border.bottom_right.y) / 2.0
         };
 
-        let new_quad = Quad::from(top_right_corner_1, top_right_corner_2, self.capacity);
+        let new_quad = self.child(top_right_corner_1, top_right_corner_2);
         self.top_right_quad = Some(Box::new(new_quad));
 
         let bottom_right_corner_1 = Point{
@@ -106,276 +249,1761 @@ impl Quad {
             y: self.border.bottom_right.y
         };
 
-        let new_quad = Quad::from(bottom_right_corner_1, bottom_right_corner_2, self.capacity);
+        let new_quad = self.child(bottom_right_corner_1, bottom_right_corner_2);
         self.bottom_right_quad = Some(Box::new(new_quad));
     }
 
-    pub fn insert(&mut self, point: &Point) {
-        if !self.check_boundary(&point) {
-            return
-        }
+    /// Inserts with the next free id, returning the id of the stored entry
+    pub fn insert(&mut self, point: &Point, value: V) -> Result<u64, InsertError> {
+        let id = self.next_id;
 
-        if self.is_leaf {
-            if (self.points.len() as i64) < self.capacity {
-                self.points.push(point.to_owned());
-            }
-            else {
-                self.subdivide();
-
-                for existing_point in &self.points {
-                    self.top_left_quad.as_mut().unwrap().insert(existing_point);
-                    self.bottom_left_quad.as_mut().unwrap().insert(existing_point);
-                    self.top_right_quad.as_mut().unwrap().insert(existing_point);
-                    self.bottom_right_quad.as_mut().unwrap().insert(existing_point);
-                }
+        return self.insert_with_id(id, point, value);
+    }
 
-                self.points.clear();
-                self.is_leaf = false;
+    /// Inserts with a caller chosen id. Under `DuplicatePolicy::Count` the id
+    /// of the existing entry is returned instead.
+    pub fn insert_with_id(&mut self, id: u64, point: &Point, value: V) -> Result<u64, InsertError> {
+        let next_id = match id.checked_add(1) {
+            Some(next_id) => next_id,
+            None => return Err(InsertError::IdReserved(id))
+        };
 
-                self.top_left_quad.as_mut().unwrap().insert(point);
-                self.bottom_left_quad.as_mut().unwrap().insert(point);
-                self.top_right_quad.as_mut().unwrap().insert(point);
-                self.bottom_right_quad.as_mut().unwrap().insert(point);
-            }
+        if self.ids.contains_key(&id) {
+            return Err(InsertError::IdInUse(id))
         }
-        else {
-            self.top_left_quad.as_mut().unwrap().insert(point);
-            self.bottom_left_quad.as_mut().unwrap().insert(point);
-            self.top_right_quad.as_mut().unwrap().insert(point);
-            self.bottom_right_quad.as_mut().unwrap().insert(point);
-        }
-    }
 
-    pub fn delete(&mut self, point: &Point) {
-        if !self.check_boundary(&point) {
-            return
+        if self.growable {
+            self.grow_to_fit(point);
         }
 
-        if self.is_leaf {
-            self.points.retain(|x| *x != *point);
-        }
-        else {
-            self.top_left_quad.as_mut().unwrap().delete(point);
-            self.bottom_left_quad.as_mut().unwrap().delete(point);
-            self.top_right_quad.as_mut().unwrap().delete(point);
-            self.bottom_right_quad.as_mut().unwrap().delete(point);
+        if !self.check_boundary(point) {
+            return Err(InsertError::OutOfBounds)
         }
-    }
 
-    pub fn find_within_range(&self, circle: &Circle) -> Vec<Point> {
-        let mut points = vec![];
+        let duplicate_policy = self.duplicate_policy;
 
-        self.find_within_range_helper(circle, &mut points);
+        if duplicate_policy != DuplicatePolicy::Allow {
+            let existing = self.leaf_for_mut(point).entries
+                .iter_mut()
+                .find(|e| e.point == *point);
 
-        return points;
-    }
+            if let Some(existing) = existing {
+                if duplicate_policy == DuplicatePolicy::Reject {
+                    return Err(InsertError::Duplicate(existing.id))
+                }
 
-    fn find_within_range_helper(&self, circle: &Circle, points: &mut Vec<Point>) {
-        if circle.intersects(&self.border) {
-            if !self.is_leaf {
-                self.top_left_quad.as_ref().unwrap().find_within_range_helper(circle, points);
-                self.top_right_quad.as_ref().unwrap().find_within_range_helper(circle, points);
-                self.bottom_left_quad.as_ref().unwrap().find_within_range_helper(circle, points);
-                self.bottom_right_quad.as_ref().unwrap().find_within_range_helper(circle, points);
-            }
-            else {
-                let mut found_points = self.points
-                    .iter()
-                    .filter(|p| circle.contains(p))
-                    .map(|p| p.clone())
-                    .collect::<Vec<Point>>();
-
-                points.append(&mut found_points);
+                existing.count += 1;
+                return Ok(existing.id);
             }
         }
+
+        self.insert_helper(Entry{
+            id,
+            point: *point,
+            value,
+            count: 1
+        });
+
+        self.ids.insert(id, *point);
+        self.next_id = u64::max(self.next_id, next_id);
+
+        return Ok(id);
     }
 
-    pub fn search(&self, point: &Point) -> bool {
-        if !self.check_boundary(point) {
-            return false
+    /// Builds a tree from a full dataset in a single top-down pass instead of
+    /// inserting point by point. Entries receive ids in input order, starting at 0.
+    pub fn bulk_load(bounds: Rectangle, capacity: i64, entries: Vec<(Point, V)>) -> Result<Quad<V>, InsertError> {
+        let mut quad = Quad::from(bounds.top_left, bounds.bottom_right, capacity);
+
+        if entries.iter().any(|(point, _)| !quad.check_boundary(point)) {
+            return Err(InsertError::OutOfBounds)
         }
 
-        if self.is_leaf && self.points.contains(point) {
-            return true
+        quad.insert_bulk(entries);
+
+        return Ok(quad);
+    }
+
+    /// Inserts many entries at once, returning a result per entry. An empty tree
+    /// that allows duplicates is built top-down; otherwise each entry is inserted
+    /// in turn.
+    pub fn insert_bulk(&mut self, entries: Vec<(Point, V)>) -> Vec<Result<u64, InsertError>> {
+        if !self.is_leaf || !self.entries.is_empty() || self.duplicate_policy != DuplicatePolicy::Allow || self.growable {
+            return entries
+                .into_iter()
+                .map(|(point, value)| self.insert(&point, value))
+                .collect();
         }
 
-        if (self.border.top_left.x + self.border.bottom_right.x) / 2.0 >= point.x {
-            if (self.border.top_left.y + self.border.bottom_right.y) / 2.0 >= point.y {
-                if self.top_left_quad.is_none() {
-                    return false
-                }
+        let mut results = Vec::with_capacity(entries.len());
+        let mut accepted = Vec::with_capacity(entries.len());
 
-                let quad = self.top_left_quad.as_ref().unwrap();
-                return quad.search(point)
+        for (point, value) in entries {
+            if !self.check_boundary(&point) {
+                results.push(Err(InsertError::OutOfBounds));
+                continue
             }
-            else {
-                if self.bottom_left_quad.is_none() {
-                    return false
+
+            let id = self.next_id;
+
+            self.next_id = match id.checked_add(1) {
+                Some(next_id) => next_id,
+                None => {
+                    results.push(Err(InsertError::IdReserved(id)));
+                    continue
                 }
+            };
+            self.ids.insert(id, point);
+
+            accepted.push(Entry{
+                id,
+                point,
+                value,
+                count: 1
+            });
+            results.push(Ok(id));
+        }
 
-                let quad = self.bottom_right_quad.as_ref().unwrap();
-                return quad.search(point)
+        self.build(accepted);
+
+        return results;
+    }
+
+    fn build(&mut self, entries: Vec<Entry<V>>) {
+        self.size = entries.len();
+
+        if (entries.len() as i64) <= self.capacity || self.depth >= self.max_depth {
+            self.entries = entries;
+            return
+        }
+
+        self.subdivide();
+        self.is_leaf = false;
+
+        let split = self.split_point();
+        let mut top_left = vec![];
+        let mut top_right = vec![];
+        let mut bottom_left = vec![];
+        let mut bottom_right = vec![];
+
+        for entry in entries {
+            match Quadrant::of(&split, &entry.point) {
+                Quadrant::TopLeft => top_left.push(entry),
+                Quadrant::TopRight => top_right.push(entry),
+                Quadrant::BottomLeft => bottom_left.push(entry),
+                Quadrant::BottomRight => bottom_right.push(entry)
             }
         }
-        else {
-            if (self.border.top_left.y + self.border.bottom_right.y) / 2.0 >= point.y {
-                if self.top_right_quad.is_none() {
-                    return false;
-                }
 
-                let quad = self.top_right_quad.as_ref().unwrap();
-                return quad.search(point)
+        self.top_left_quad.as_mut().unwrap().build(top_left);
+        self.top_right_quad.as_mut().unwrap().build(top_right);
+        self.bottom_left_quad.as_mut().unwrap().build(bottom_left);
+        self.bottom_right_quad.as_mut().unwrap().build(bottom_right);
+    }
+
+    fn insert_helper(&mut self, entry: Entry<V>) {
+        self.size += 1;
+
+        if self.is_leaf {
+            if (self.entries.len() as i64) < self.capacity || self.depth >= self.max_depth {
+                self.entries.push(entry);
+                return
             }
-            else {
-                if self.bottom_right_quad.is_none() {
-                    return false;
-                }
 
-                let quad = self.bottom_right_quad.as_ref().unwrap();
-                return quad.search(point)
+            self.subdivide();
+            self.is_leaf = false;
+
+            for existing_entry in std::mem::take(&mut self.entries) {
+                self.child_for_mut(&existing_entry.point).insert_helper(existing_entry);
             }
         }
-    }
 
-    fn check_boundary(&self, point: &Point) -> bool {
-        return point.x >= self.border.top_left.x && point.x <= self.border.bottom_right.x && 
-                point.y >= self.border.top_left.y && point.y <= self.border.bottom_right.y
+        self.child_for_mut(&entry.point).insert_helper(entry);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::circle::Circle;
+    fn grow_to_fit(&mut self, point: &Point) {
+        if !point.x.is_finite() || !point.y.is_finite() {
+            return
+        }
 
-    use super::Quad;
-    use super::Point;
+        while !self.check_boundary(point) {
+            let width = self.border.bottom_right.x - self.border.top_left.x;
+            let height = self.border.bottom_right.y - self.border.top_left.y;
 
+            if width <= 0.0 || height <= 0.0 {
+                return
+            }
 
-    #[test]
-    fn test_insert_and_get() {
-        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+            self.grow(point.x < self.border.top_left.x, point.y < self.border.top_left.y);
+        }
+    }
 
-        let existant_point = Point{
-            x: 5.0,
-            y: 5.0
+    // Wraps the root in a parent of twice its extent. The existing tree
+    // becomes one of the parent's quadrants, keeping its borders intact.
+    fn grow(&mut self, grow_left: bool, grow_up: bool) {
+        let old_border = self.border;
+
+        let width = old_border.bottom_right.x - old_border.top_left.x;
+        let height = old_border.bottom_right.y - old_border.top_left.y;
+
+        let top_left = Point{
+            x: if grow_left { old_border.top_left.x - width } else { old_border.top_left.x },
+            y: if grow_up { old_border.top_left.y - height } else { old_border.top_left.y }
         };
-        let nonexistant_point = Point{
-            x: 7.0,
-            y: 7.0
+        let bottom_right = Point{
+            x: if grow_left { old_border.bottom_right.x } else { old_border.bottom_right.x + width },
+            y: if grow_up { old_border.bottom_right.y } else { old_border.bottom_right.y + height }
+        };
+        let center = Point{
+            x: if grow_left { old_border.top_left.x } else { old_border.bottom_right.x },
+            y: if grow_up { old_border.top_left.y } else { old_border.bottom_right.y }
         };
 
-        quad.insert(&existant_point);
+        let parent = Quad::from(top_left, bottom_right, self.capacity)
+            .with_max_depth(self.max_depth)
+            .with_duplicate_policy(self.duplicate_policy)
+            .with_growable(self.growable);
+
+        let mut old_root = std::mem::replace(self, parent);
+
+        self.ids = std::mem::take(&mut old_root.ids);
+        self.next_id = old_root.next_id;
+        self.size = old_root.size;
+
+        old_root.increment_depth();
+
+        self.top_left_quad = Some(Box::new(self.child(top_left, center)));
+        self.top_right_quad = Some(Box::new(self.child(
+            Point{x: center.x, y: top_left.y},
+            Point{x: bottom_right.x, y: center.y}
+        )));
+        self.bottom_left_quad = Some(Box::new(self.child(
+            Point{x: top_left.x, y: center.y},
+            Point{x: center.x, y: bottom_right.y}
+        )));
+        self.bottom_right_quad = Some(Box::new(self.child(center, bottom_right)));
+
+        let slot = match (grow_left, grow_up) {
+            (true, true) => &mut self.bottom_right_quad,
+            (true, false) => &mut self.top_right_quad,
+            (false, true) => &mut self.bottom_left_quad,
+            (false, false) => &mut self.top_left_quad
+        };
+        *slot = Some(Box::new(old_root));
 
-        let existant_point_search = quad.search(&existant_point);
-        let non_existant_point_search = quad.search(&nonexistant_point);
+        self.is_leaf = false;
+    }
+
+    fn increment_depth(&mut self) {
+        self.depth += 1;
 
-        assert_eq!(existant_point_search, true);
-        assert_eq!(non_existant_point_search, false);
+        for child in [&mut self.top_left_quad, &mut self.top_right_quad, &mut self.bottom_left_quad, &mut self.bottom_right_quad].into_iter().flatten() {
+            child.increment_depth();
+        }
     }
 
-    #[test]
-    fn test_insert_and_remove() {
-        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+    /// Removes every entry at the point, returning their values. The length of
+    /// the result is the number of points deleted. Under `DuplicatePolicy::Count`
+    /// one delete undoes one insert: a merged entry loses one from its count and
+    /// is only removed once that reaches zero.
+    pub fn delete(&mut self, point: &Point) -> Vec<V> where V: Clone {
+        if !self.check_boundary(point) {
+            return vec![]
+        }
 
-        let point = Point{
-            x: 5.0,
-            y: 5.0
-        };
+        if self.duplicate_policy == DuplicatePolicy::Count {
+            let merged = self.leaf_for_mut(point).entries
+                .iter_mut()
+                .find(|e| e.point == *point && e.count > 1);
 
-        quad.insert(&point);
-        quad.delete(&point);
+            if let Some(entry) = merged {
+                entry.count -= 1;
+                return vec![entry.value.clone()];
+            }
+        }
 
-        let point_search = quad.search(&point);
+        let removed = self.remove_helper(point, &|e| e.point == *point);
 
-        assert_eq!(point_search, false);
+        for entry in &removed {
+            self.ids.remove(&entry.id);
+        }
+
+        return removed.into_iter().map(|e| e.value).collect();
     }
 
-    #[test]
-    fn test_insert_and_get_with_subdivide() {
-        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+    pub fn get_by_id(&self, id: u64) -> Option<&Entry<V>> {
+        let point = self.ids.get(&id)?;
 
-        let existant_point = Point{
-            x: 5.0,
-            y: 5.0
-        };
-        let nonexistant_point = Point{
-            x: 7.0,
-            y: 7.0
+        return self.leaf_for(point).entries
+            .iter()
+            .find(|e| e.id == id);
+    }
+
+    pub fn delete_by_id(&mut self, id: u64) -> Option<V> {
+        let point = self.ids.remove(&id)?;
+
+        return self.take_from_leaf(id, &point).map(|e| e.value);
+    }
+
+    /// Moves an entry to a new point, following the duplicate policy if another
+    /// entry is already there: `Reject` leaves the entry where it was and
+    /// `Count` merges it into the existing entry, whose id is kept.
+    pub fn move_by_id(&mut self, id: u64, new_point: &Point) -> bool {
+        if !self.ids.contains_key(&id) {
+            return false
+        }
+
+        if self.growable {
+            self.grow_to_fit(new_point);
+        }
+
+        if !self.check_boundary(new_point) {
+            return false
+        }
+
+        let point = match self.ids.get(&id) {
+            Some(p) => *p,
+            None => return false
         };
 
-        quad.insert(&existant_point);
+        let duplicate_policy = self.duplicate_policy;
 
-        let existant_point_search = quad.search(&existant_point);
-        let non_existant_point_search = quad.search(&nonexistant_point);
+        if duplicate_policy != DuplicatePolicy::Allow {
+            let occupied = self.leaf_for(new_point).entries
+                .iter()
+                .any(|e| e.point == *new_point && e.id != id);
+
+            if occupied {
+                if duplicate_policy == DuplicatePolicy::Reject {
+                    return false
+                }
+
+                let entry = self.take_from_leaf(id, &point).unwrap();
+                self.ids.remove(&id);
+
+                // Taking the entry may have collapsed nodes, so find the leaf again
+                let existing = self.leaf_for_mut(new_point).entries
+                    .iter_mut()
+                    .find(|e| e.point == *new_point)
+                    .unwrap();
+
+                existing.count += entry.count;
+                return true;
+            }
+        }
+
+        let mut entry = self.take_from_leaf(id, &point).unwrap();
+        entry.point = *new_point;
 
-        assert_eq!(existant_point_search, true);
-        assert_eq!(non_existant_point_search, false);
+        self.insert_helper(entry);
+        self.ids.insert(id, *new_point);
+
+        return true;
     }
 
-    #[test]
-    fn test_insert_and_remove_with_subdivide() {
-        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+    fn take_from_leaf(&mut self, id: u64, point: &Point) -> Option<Entry<V>> {
+        return self.remove_helper(point, &|e| e.id == id).pop();
+    }
 
-        let point = Point{
-            x: 5.0,
-            y: 5.0
-        };
+    // Removes the matching entries from the leaf owning `point`, collapsing
+    // nodes on the way back up once their children are no longer needed
+    fn remove_helper<F: Fn(&Entry<V>) -> bool>(&mut self, point: &Point, predicate: &F) -> Vec<Entry<V>> {
+        if self.is_leaf {
+            let (removed, kept): (Vec<Entry<V>>, Vec<Entry<V>>) = std::mem::take(&mut self.entries)
+                .into_iter()
+                .partition(predicate);
 
-        quad.insert(&point);
-        quad.delete(&point);
+            self.entries = kept;
+            self.size -= removed.len();
 
-        let point_search = quad.search(&point);
+            return removed;
+        }
+
+        let removed = self.child_for_mut(point).remove_helper(point, predicate);
+        self.size -= removed.len();
+
+        if !removed.is_empty() {
+            self.collapse();
+        }
 
-        assert_eq!(point_search, false);
+        return removed;
     }
 
-    #[test]
-    fn test_find_within_valid_range() {
-        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+    // Merges the four children back into this node when they are all leaves
+    // holding fewer than `capacity` entries between them
+    fn collapse(&mut self) {
+        let children = [&self.top_left_quad, &self.top_right_quad, &self.bottom_left_quad, &self.bottom_right_quad];
 
-        let point = Point{
-            x: 5.0,
-            y: 5.0
-        };
+        if !children.iter().all(|c| c.as_ref().unwrap().is_leaf) {
+            return
+        }
 
-        quad.insert(&point);
+        let count: usize = children.iter().map(|c| c.as_ref().unwrap().entries.len()).sum();
 
-        let circle = Circle{
-            center: Point{
-                x: 0.0,
-                y: 0.0
-            },
-            radius: 10.0
-        };
+        if count as i64 >= self.capacity {
+            return
+        }
 
-        let points = quad.find_within_range(&circle);
+        for child in [&mut self.top_left_quad, &mut self.top_right_quad, &mut self.bottom_left_quad, &mut self.bottom_right_quad] {
+            let child = child.take().unwrap();
+            self.entries.extend(child.entries);
+        }
 
-        assert_eq!(points.len(), 1);
-        assert_eq!(points[0], point);
+        self.is_leaf = true;
     }
 
-    #[test]
-    fn test_find_within_invalid_range() {
-        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+    /// Number of entries in the tree. Points merged under
+    /// `DuplicatePolicy::Count` are a single entry.
+    pub fn len(&self) -> usize {
+        return self.size;
+    }
 
-        let point = Point{
-            x: 5.0,
-            y: 5.0
-        };
+    pub fn is_empty(&self) -> bool {
+        return self.size == 0;
+    }
 
-        quad.insert(&point);
+    pub fn node_count(&self) -> usize {
+        if self.is_leaf {
+            return 1
+        }
 
-        let circle = Circle{
-            center: Point{
-                x: 0.0,
-                y: 0.0
-            },
-            radius: 1.0
-        };
+        return 1 + self.top_left_quad.as_ref().unwrap().node_count() +
+                self.top_right_quad.as_ref().unwrap().node_count() +
+                self.bottom_left_quad.as_ref().unwrap().node_count() +
+                self.bottom_right_quad.as_ref().unwrap().node_count()
+    }
 
-        let points = quad.find_within_range(&circle);
+    /// Lazily yields every entry in the tree
+    pub fn iter(&self) -> Iter<'_, V> {
+        return Iter::new(self);
+    }
 
-        assert_eq!(points.len(), 0);
+    /// Lazily yields every node in the tree along with its depth and border
+    pub fn nodes(&self) -> Nodes<'_, V> {
+        return Nodes::new(self);
     }
-}
 
+    pub fn query<S: Shape>(&self, shape: &S) -> Vec<&Entry<V>> {
+        return self.query_iter(shape).collect();
+    }
+
+    /// Lazily yields the entries within the shape as the tree is walked
+    pub fn query_iter<'s, S: Shape>(&self, shape: &'s S) -> QueryIter<'_, 's, V, S> {
+        return QueryIter::new(self, shape);
+    }
+
+    pub fn find_within_range(&self, circle: &Circle) -> Vec<&Entry<V>> {
+        return self.query(circle);
+    }
+
+    pub fn find_within_rectangle(&self, rectangle: &Rectangle) -> Vec<&Entry<V>> {
+        return self.query(rectangle);
+    }
+
+    pub fn find_within_polygon(&self, polygon: &Polygon) -> Vec<&Entry<V>> {
+        return self.query(polygon);
+    }
+
+    /// Counts the entries within the shape without collecting them. Nodes the
+    /// shape fully covers are counted from their cached size.
+    pub fn count<S: Shape>(&self, shape: &S) -> usize {
+        if !shape.intersects(&self.border) {
+            return 0
+        }
+
+        if shape.contains_rect(&self.border) {
+            return self.size
+        }
+
+        if self.is_leaf {
+            return self.entries.iter().filter(|e| shape.contains(&e.point)).count()
+        }
+
+        return self.top_left_quad.as_ref().unwrap().count(shape) +
+                self.top_right_quad.as_ref().unwrap().count(shape) +
+                self.bottom_left_quad.as_ref().unwrap().count(shape) +
+                self.bottom_right_quad.as_ref().unwrap().count(shape)
+    }
+
+    pub fn count_within(&self, circle: &Circle) -> usize {
+        return self.count(circle);
+    }
+
+    pub fn count_within_rectangle(&self, rectangle: &Rectangle) -> usize {
+        return self.count(rectangle);
+    }
+
+    pub fn count_within_polygon(&self, polygon: &Polygon) -> usize {
+        return self.count(polygon);
+    }
+
+    /// Returns up to `k` entries closest to the point, nearest first, along with their distance
+    pub fn nearest(&self, point: &Point, k: usize) -> Vec<(&Entry<V>, f64)> {
+        let mut neighbours = vec![];
+        let mut queue = BinaryHeap::new();
+
+        queue.push(Candidate{
+            distance: self.border.distance(point),
+            item: CandidateItem::Quad(self)
+        });
+
+        while neighbours.len() < k {
+            let candidate = match queue.pop() {
+                Some(c) => c,
+                None => break
+            };
+
+            match candidate.item {
+                CandidateItem::Entry(entry) => {
+                    neighbours.push((entry, candidate.distance));
+                }
+                CandidateItem::Quad(quad) if quad.is_leaf => {
+                    for entry in &quad.entries {
+                        queue.push(Candidate{
+                            distance: entry.point.distance(point),
+                            item: CandidateItem::Entry(entry)
+                        });
+                    }
+                }
+                CandidateItem::Quad(quad) => {
+                    for child in [&quad.top_left_quad, &quad.top_right_quad, &quad.bottom_left_quad, &quad.bottom_right_quad] {
+                        let child = child.as_ref().unwrap();
+
+                        queue.push(Candidate{
+                            distance: child.border.distance(point),
+                            item: CandidateItem::Quad(child)
+                        });
+                    }
+                }
+            }
+        }
+
+        return neighbours;
+    }
+
+    pub fn search(&self, point: &Point) -> Option<&V> {
+        if !self.check_boundary(point) {
+            return None
+        }
+
+        return self.leaf_for(point).entries
+            .iter()
+            .find(|e| e.point == *point)
+            .map(|e| &e.value);
+    }
+
+    fn leaf_for(&self, point: &Point) -> &Quad<V> {
+        if self.is_leaf {
+            return self
+        }
+
+        return self.child_for(point).leaf_for(point);
+    }
+
+    fn leaf_for_mut(&mut self, point: &Point) -> &mut Quad<V> {
+        if self.is_leaf {
+            return self
+        }
+
+        return self.child_for_mut(point).leaf_for_mut(point);
+    }
+
+    fn child_for(&self, point: &Point) -> &Quad<V> {
+        let child = match Quadrant::of(&self.split_point(), point) {
+            Quadrant::TopLeft => &self.top_left_quad,
+            Quadrant::TopRight => &self.top_right_quad,
+            Quadrant::BottomLeft => &self.bottom_left_quad,
+            Quadrant::BottomRight => &self.bottom_right_quad
+        };
+
+        return child.as_ref().unwrap();
+    }
+
+    fn child_for_mut(&mut self, point: &Point) -> &mut Quad<V> {
+        let child = match Quadrant::of(&self.split_point(), point) {
+            Quadrant::TopLeft => &mut self.top_left_quad,
+            Quadrant::TopRight => &mut self.top_right_quad,
+            Quadrant::BottomLeft => &mut self.bottom_left_quad,
+            Quadrant::BottomRight => &mut self.bottom_right_quad
+        };
+
+        return child.as_mut().unwrap();
+    }
+
+    // Taken from the children rather than recomputed from the border so that
+    // routing always agrees with the child borders, including after growth
+    fn split_point(&self) -> Point {
+        return self.top_left_quad.as_ref().unwrap().border.bottom_right;
+    }
+
+    fn check_boundary(&self, point: &Point) -> bool {
+        return self.border.contains(point);
+    }
+}
+
+// Snapshot layout, all numbers little endian:
+//   magic "QUAD", version u32
+//   border (top left x, y, bottom right x, y) f64 x4, capacity i64,
+//   max_depth i64, duplicate policy u8, growable u8, next id u64
+//   entry count u64, then per entry: id u64, x f64, y f64, count u64, value
+// Only entries are stored; the node layout is rebuilt top-down on load.
+impl<V: Codec> Quad<V> {
+    /// Writes a versioned binary snapshot of the tree and its settings
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        SNAPSHOT_VERSION.encode(writer)?;
+
+        self.border.top_left.x.encode(writer)?;
+        self.border.top_left.y.encode(writer)?;
+        self.border.bottom_right.x.encode(writer)?;
+        self.border.bottom_right.y.encode(writer)?;
+        self.capacity.encode(writer)?;
+        self.max_depth.encode(writer)?;
+        self.duplicate_policy.encode(writer)?;
+        self.growable.encode(writer)?;
+        self.next_id.encode(writer)?;
+
+        (self.size as u64).encode(writer)?;
+
+        for entry in self.iter() {
+            entry.id.encode(writer)?;
+            entry.point.x.encode(writer)?;
+            entry.point.y.encode(writer)?;
+            entry.count.encode(writer)?;
+            entry.value.encode(writer)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Reads a tree written by `save_to`, keeping every entry's id
+    pub fn load_from<R: Read>(reader: &mut R) -> Result<Quad<V>, SnapshotError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::NotASnapshot)
+        }
+
+        let version = u32::decode(reader)?;
+
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version))
+        }
+
+        let top_left = Point{
+            x: f64::decode(reader)?,
+            y: f64::decode(reader)?
+        };
+        let bottom_right = Point{
+            x: f64::decode(reader)?,
+            y: f64::decode(reader)?
+        };
+
+        if ![top_left.x, top_left.y, bottom_right.x, bottom_right.y].iter().all(|c| c.is_finite()) {
+            return Err(SnapshotError::Corrupt(String::from("the tree bounds are not finite")))
+        }
+
+        if top_left.x >= bottom_right.x || top_left.y >= bottom_right.y {
+            return Err(SnapshotError::Corrupt(String::from("the top left corner is not above and left of the bottom right corner")))
+        }
+
+        let capacity = i64::decode(reader)?;
+
+        if capacity <= 0 {
+            return Err(SnapshotError::Corrupt(format!("capacity {} is not positive", capacity)))
+        }
+
+        let max_depth = i64::decode(reader)?;
+
+        if !(0..=MAX_DEPTH).contains(&max_depth) {
+            return Err(SnapshotError::Corrupt(format!("max depth {} is outside 0 to {}", max_depth, MAX_DEPTH)))
+        }
+
+        let mut quad = Quad::from(top_left, bottom_right, capacity)
+            .with_max_depth(max_depth)
+            .with_duplicate_policy(DuplicatePolicy::decode(reader)?)
+            .with_growable(bool::decode(reader)?);
+
+        let next_id = u64::decode(reader)?;
+        let len = u64::decode(reader)?;
+
+        let mut entries = vec![];
+
+        for _ in 0..len {
+            let entry = Entry{
+                id: u64::decode(reader)?,
+                point: Point{
+                    x: f64::decode(reader)?,
+                    y: f64::decode(reader)?
+                },
+                count: u64::decode(reader)?,
+                value: V::decode(reader)?
+            };
+
+            if !quad.check_boundary(&entry.point) {
+                return Err(SnapshotError::Corrupt(format!("entry {} lies outside the tree bounds", entry.id)))
+            }
+
+            if quad.ids.insert(entry.id, entry.point).is_some() {
+                return Err(SnapshotError::Corrupt(format!("id {} appears more than once", entry.id)))
+            }
+
+            let next_id = match entry.id.checked_add(1) {
+                Some(next_id) => next_id,
+                None => return Err(SnapshotError::Corrupt(format!("entry {} has a reserved id", entry.id)))
+            };
+
+            quad.next_id = u64::max(quad.next_id, next_id);
+            entries.push(entry);
+        }
+
+        quad.next_id = u64::max(quad.next_id, next_id);
+        quad.build(entries);
+
+        return Ok(quad);
+    }
+}
+
+impl<'a, V> IntoIterator for &'a Quad<V> {
+    type Item = &'a Entry<V>;
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Iter<'a, V> {
+        return self.iter();
+    }
+}
+
+// Every point belongs to exactly one child. Points lying on the dividing
+// lines go to the top and/or left quadrant.
+#[derive(Clone, Copy)]
+enum Quadrant {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight
+}
+
+impl Quadrant {
+    fn of(split: &Point, point: &Point) -> Quadrant {
+        if split.x >= point.x {
+            if split.y >= point.y { Quadrant::TopLeft } else { Quadrant::BottomLeft }
+        }
+        else if split.y >= point.y {
+            Quadrant::TopRight
+        }
+        else {
+            Quadrant::BottomRight
+        }
+    }
+}
+
+enum CandidateItem<'a, V> {
+    Quad(&'a Quad<V>),
+    Entry(&'a Entry<V>)
+}
+
+// Queue element for the best-first walk in `Quad::nearest`. Ordered so that
+// the `BinaryHeap` pops the smallest distance first.
+struct Candidate<'a, V> {
+    distance: f64,
+    item: CandidateItem<'a, V>
+}
+
+impl<V> PartialEq for Candidate<'_, V> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.distance == other.distance;
+    }
+}
+
+impl<V> Eq for Candidate<'_, V> {}
+
+impl<V> PartialOrd for Candidate<'_, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl<V> Ord for Candidate<'_, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other.distance.total_cmp(&self.distance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circle::Circle;
+    use crate::duplicate_policy::DuplicatePolicy;
+    use crate::insert_error::InsertError;
+    use crate::polygon::Polygon;
+    use crate::rectangle::Rectangle;
+    use crate::shape::Shape;
+    use crate::snapshot_error::SnapshotError;
+
+    use super::{Quad, MAX_DEPTH};
+    use super::Point;
+
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut quad: Quad<()> = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        let existant_point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+        let nonexistant_point = Point{
+            x: 7.0,
+            y: 7.0
+        };
+
+        quad.insert(&existant_point, ()).unwrap();
+
+        let existant_point_search = quad.search(&existant_point);
+        let non_existant_point_search = quad.search(&nonexistant_point);
+
+        assert!(existant_point_search.is_some());
+        assert!(non_existant_point_search.is_none());
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut quad: Quad<()> = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        quad.insert(&point, ()).unwrap();
+        quad.delete(&point);
+
+        let point_search = quad.search(&point);
+
+        assert!(point_search.is_none());
+    }
+
+    #[test]
+    fn test_insert_and_get_with_subdivide() {
+        let mut quad: Quad<()> = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let existant_point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+        let nonexistant_point = Point{
+            x: 7.0,
+            y: 7.0
+        };
+
+        quad.insert(&existant_point, ()).unwrap();
+
+        let existant_point_search = quad.search(&existant_point);
+        let non_existant_point_search = quad.search(&nonexistant_point);
+
+        assert!(existant_point_search.is_some());
+        assert!(non_existant_point_search.is_none());
+    }
+
+    #[test]
+    fn test_insert_and_remove_with_subdivide() {
+        let mut quad: Quad<()> = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        quad.insert(&point, ()).unwrap();
+        quad.delete(&point);
+
+        let point_search = quad.search(&point);
+
+        assert!(point_search.is_none());
+    }
+
+    #[test]
+    fn test_find_within_valid_range() {
+        let mut quad: Quad<()> = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        quad.insert(&point, ()).unwrap();
+
+        let circle = Circle{
+            center: Point{
+                x: 0.0,
+                y: 0.0
+            },
+            radius: 10.0
+        };
+
+        let points = quad.find_within_range(&circle);
+
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].point, point);
+    }
+
+    #[test]
+    fn test_find_within_invalid_range() {
+        let mut quad: Quad<()> = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        quad.insert(&point, ()).unwrap();
+
+        let circle = Circle{
+            center: Point{
+                x: 0.0,
+                y: 0.0
+            },
+            radius: 1.0
+        };
+
+        let points = quad.find_within_range(&circle);
+
+        assert_eq!(points.len(), 0);
+    }
+
+    #[test]
+    fn test_insert_and_get_payload() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let point1 = Point{
+            x: 5.0,
+            y: 5.0
+        };
+        let point2 = Point{
+            x: 60.0,
+            y: 60.0
+        };
+
+        quad.insert(&point1, "first").unwrap();
+        quad.insert(&point2, "second").unwrap();
+
+        assert_eq!(quad.search(&point1), Some(&"first"));
+        assert_eq!(quad.search(&point2), Some(&"second"));
+        assert_eq!(quad.delete(&point2), vec!["second"]);
+        assert!(quad.search(&point2).is_none());
+    }
+
+    #[test]
+    fn test_find_within_range_on_dividing_line() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let center = Point{
+            x: 50.0,
+            y: 50.0
+        };
+
+        quad.insert(&Point{x: 10.0, y: 10.0}, 1).unwrap();
+        quad.insert(&center, 2).unwrap();
+
+        let circle = Circle{
+            center,
+            radius: 1.0
+        };
+
+        let points = quad.find_within_range(&circle);
+
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].value, 2);
+        assert_eq!(quad.search(&center), Some(&2));
+    }
+
+    #[test]
+    fn test_delete_by_id_at_shared_location() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        let first = quad.insert(&point, "first").unwrap();
+        let second = quad.insert(&point, "second").unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(quad.delete_by_id(first), Some("first"));
+        assert_eq!(quad.delete_by_id(first), None);
+        assert!(quad.get_by_id(first).is_none());
+        assert_eq!(quad.get_by_id(second).unwrap().value, "second");
+    }
+
+    #[test]
+    fn test_move_by_id() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        let old_point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+        let new_point = Point{
+            x: 90.0,
+            y: 90.0
+        };
+
+        let id = quad.insert(&old_point, ()).unwrap();
+        quad.insert(&Point{x: 10.0, y: 10.0}, ()).unwrap();
+
+        assert!(quad.move_by_id(id, &new_point));
+        assert!(!quad.move_by_id(id, &Point{x: 200.0, y: 200.0}));
+        assert!(!quad.move_by_id(id + 100, &old_point));
+
+        assert!(quad.search(&old_point).is_none());
+        assert!(quad.search(&new_point).is_some());
+        assert_eq!(quad.get_by_id(id).unwrap().point, new_point);
+    }
+
+    #[test]
+    fn test_move_by_id_duplicate_policy() {
+        let first_point = Point{x: 5.0, y: 5.0};
+        let second_point = Point{x: 90.0, y: 90.0};
+
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_duplicate_policy(DuplicatePolicy::Reject);
+
+        let first = quad.insert(&first_point, ()).unwrap();
+        let second = quad.insert(&second_point, ()).unwrap();
+
+        assert!(!quad.move_by_id(first, &second_point));
+        assert_eq!(quad.get_by_id(first).unwrap().point, first_point);
+        assert_eq!(quad.len(), 2);
+
+        // Moving onto its own point is not a duplicate
+        assert!(quad.move_by_id(second, &second_point));
+
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_duplicate_policy(DuplicatePolicy::Count);
+
+        let first = quad.insert(&first_point, ()).unwrap();
+        quad.insert(&first_point, ()).unwrap();
+        let second = quad.insert(&second_point, ()).unwrap();
+
+        assert!(quad.move_by_id(first, &second_point));
+        assert!(quad.get_by_id(first).is_none());
+        assert!(quad.search(&first_point).is_none());
+        assert_eq!(quad.len(), 1);
+        assert_eq!(quad.get_by_id(second).unwrap().count, 3);
+    }
+
+    #[test]
+    fn test_insert_with_id() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        assert_eq!(quad.insert_with_id(7, &point, ()), Ok(7));
+        assert_eq!(quad.insert_with_id(7, &point, ()), Err(InsertError::IdInUse(7)));
+        assert_eq!(quad.insert(&point, ()), Ok(8));
+    }
+
+    #[test]
+    fn test_insert_with_reserved_id() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        assert_eq!(quad.insert_with_id(u64::MAX, &Point{x: 5.0, y: 5.0}, ()), Err(InsertError::IdReserved(u64::MAX)));
+        assert_eq!(quad.len(), 0);
+
+        assert_eq!(quad.insert_with_id(u64::MAX - 1, &Point{x: 5.0, y: 5.0}, ()), Ok(u64::MAX - 1));
+        assert_eq!(quad.insert(&Point{x: 10.0, y: 10.0}, ()), Err(InsertError::IdReserved(u64::MAX)));
+        assert_eq!(quad.len(), 1);
+    }
+
+    #[test]
+    fn test_find_within_rectangle() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 5.0, y: 5.0}, 1).unwrap();
+        quad.insert(&Point{x: 20.0, y: 30.0}, 2).unwrap();
+        quad.insert(&Point{x: 75.0, y: 60.0}, 3).unwrap();
+        quad.insert(&Point{x: 90.0, y: 10.0}, 4).unwrap();
+
+        let rectangle = Rectangle::from(&Point{x: 10.0, y: 10.0}, &Point{x: 80.0, y: 60.0});
+
+        let mut values = quad.find_within_rectangle(&rectangle)
+            .iter()
+            .map(|e| e.value)
+            .collect::<Vec<i32>>();
+        values.sort();
+
+        assert_eq!(values, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_find_within_disjoint_rectangle() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 5.0, y: 5.0}, ()).unwrap();
+
+        let rectangle = Rectangle::from(&Point{x: 110.0, y: 110.0}, &Point{x: 120.0, y: 120.0});
+
+        assert_eq!(quad.find_within_rectangle(&rectangle).len(), 0);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 90.0, y: 90.0}, 1).unwrap();
+        quad.insert(&Point{x: 10.0, y: 10.0}, 2).unwrap();
+        quad.insert(&Point{x: 53.0, y: 54.0}, 3).unwrap();
+        quad.insert(&Point{x: 40.0, y: 50.0}, 4).unwrap();
+
+        let neighbours = quad.nearest(&Point{x: 50.0, y: 50.0}, 3);
+
+        let values = neighbours
+            .iter()
+            .map(|(e, _)| e.value)
+            .collect::<Vec<i32>>();
+
+        assert_eq!(values, vec![3, 4, 2]);
+        assert_eq!(neighbours[0].1, 5.0);
+        assert_eq!(neighbours[1].1, 10.0);
+    }
+
+    #[test]
+    fn test_nearest_with_fewer_entries_than_k() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        quad.insert(&Point{x: 5.0, y: 5.0}, ()).unwrap();
+
+        assert_eq!(quad.nearest(&Point{x: 0.0, y: 0.0}, 10).len(), 1);
+        assert_eq!(quad.nearest(&Point{x: 0.0, y: 0.0}, 0).len(), 0);
+    }
+
+    #[test]
+    fn test_find_within_polygon() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 10.0, y: 10.0}, 1).unwrap();
+        quad.insert(&Point{x: 20.0, y: 80.0}, 2).unwrap();
+        quad.insert(&Point{x: 50.0, y: 40.0}, 3).unwrap();
+        quad.insert(&Point{x: 85.0, y: 15.0}, 4).unwrap();
+        quad.insert(&Point{x: 90.0, y: 90.0}, 5).unwrap();
+
+        // Triangle covering the lower left half of the tree
+        let polygon = Polygon::from(vec![
+            Point{x: 0.0, y: 0.0},
+            Point{x: 100.0, y: 100.0},
+            Point{x: 0.0, y: 100.0}
+        ]);
+
+        let mut values = quad.find_within_polygon(&polygon)
+            .iter()
+            .map(|e| e.value)
+            .collect::<Vec<i32>>();
+        values.sort();
+
+        // 1 and 5 lie on the diagonal edge, which belongs to the polygon
+        assert_eq!(values, vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn test_find_within_polygon_covering_tree() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 10.0, y: 10.0}, ()).unwrap();
+        quad.insert(&Point{x: 60.0, y: 60.0}, ()).unwrap();
+        quad.insert(&Point{x: 90.0, y: 20.0}, ()).unwrap();
+
+        let polygon = Polygon::from(vec![
+            Point{x: -10.0, y: -10.0},
+            Point{x: 110.0, y: -10.0},
+            Point{x: 110.0, y: 110.0},
+            Point{x: -10.0, y: 110.0}
+        ]);
+
+        assert_eq!(quad.find_within_polygon(&polygon).len(), 3);
+    }
+
+    #[test]
+    fn test_query_iter_matches_query() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 2);
+
+        for i in 0..50 {
+            let i = i as f64;
+            quad.insert(&Point{x: i * 2.0, y: 100.0 - i * 2.0}, i).unwrap();
+        }
+
+        let circle = Circle{center: Point{x: 50.0, y: 50.0}, radius: 30.0};
+
+        let lazy = quad.query_iter(&circle).map(|e| e.id).collect::<Vec<u64>>();
+        let eager = quad.find_within_range(&circle).iter().map(|e| e.id).collect::<Vec<u64>>();
+
+        assert!(!lazy.is_empty());
+        assert_eq!(lazy, eager);
+        assert_eq!(quad.query_iter(&circle).take(3).count(), 3);
+    }
+
+    #[test]
+    fn test_iter_and_nodes() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 10.0, y: 10.0}, 1).unwrap();
+        quad.insert(&Point{x: 60.0, y: 60.0}, 2).unwrap();
+        quad.insert(&Point{x: 90.0, y: 20.0}, 3).unwrap();
+
+        let mut values = quad.iter().map(|e| e.value).collect::<Vec<i32>>();
+        values.sort();
+        assert_eq!(values, vec![1, 2, 3]);
+
+        let mut count = 0;
+        for entry in &quad {
+            assert!(quad.get_by_id(entry.id).is_some());
+            count += 1;
+        }
+        assert_eq!(count, 3);
+
+        let nodes = quad.nodes().collect::<Vec<_>>();
+        assert_eq!(nodes.len(), quad.node_count());
+        assert_eq!(nodes[0].depth, 0);
+        assert!(!nodes[0].is_leaf);
+        assert_eq!(nodes[1].depth, 1);
+        assert_eq!(*nodes[1].border, Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 50.0, y: 50.0}));
+        assert_eq!(nodes.iter().map(|n| n.entries.len()).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn test_node_accessors() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 10.0, y: 10.0}, 1).unwrap();
+        assert!(quad.is_leaf());
+        assert_eq!(quad.entries().len(), 1);
+        assert!(quad.children().iter().all(|c| c.is_none()));
+
+        quad.insert(&Point{x: 60.0, y: 60.0}, 2).unwrap();
+        assert!(!quad.is_leaf());
+        assert!(quad.entries().is_empty());
+
+        let [top_left, _, _, bottom_right] = quad.children();
+        assert_eq!(top_left.unwrap().depth(), 1);
+        assert_eq!(top_left.unwrap().entries()[0].value, 1);
+        assert_eq!(bottom_right.unwrap().entries()[0].value, 2);
+    }
+
+    fn assert_sizes<V>(quad: &Quad<V>) {
+        assert_eq!(quad.size, quad.iter().count());
+
+        for child in [&quad.top_left_quad, &quad.top_right_quad, &quad.bottom_left_quad, &quad.bottom_right_quad].into_iter().flatten() {
+            assert_sizes(child);
+        }
+    }
+
+    #[test]
+    fn test_count_within() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 2);
+
+        for i in 0..100 {
+            let i = i as f64;
+            quad.insert(&Point{x: i, y: (i * 7.0) % 100.0}, ()).unwrap();
+        }
+
+        let circle = Circle{center: Point{x: 40.0, y: 60.0}, radius: 35.0};
+        let rectangle = Rectangle::from(&Point{x: 10.0, y: 10.0}, &Point{x: 75.0, y: 55.0});
+        let everything = Rectangle::from(&Point{x: -1.0, y: -1.0}, &Point{x: 101.0, y: 101.0});
+
+        assert_eq!(quad.len(), 100);
+        assert_eq!(quad.count_within(&circle), quad.find_within_range(&circle).len());
+        assert_eq!(quad.count_within_rectangle(&rectangle), quad.find_within_rectangle(&rectangle).len());
+        assert_eq!(quad.count_within_rectangle(&everything), 100);
+        assert_sizes(&quad);
+    }
+
+    #[test]
+    fn test_sizes_follow_mutations() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, 2)
+            .with_growable(true);
+
+        let mut ids = vec![];
+        for i in 0..20 {
+            let i = i as f64;
+            ids.push(quad.insert(&Point{x: i * 0.5, y: 9.0 - i * 0.4}, ()).unwrap());
+        }
+
+        quad.insert(&Point{x: 25.0, y: -5.0}, ()).unwrap();
+        quad.delete(&Point{x: 1.0, y: 8.2});
+        quad.delete_by_id(ids[5]);
+        quad.move_by_id(ids[10], &Point{x: 1.0, y: 1.0});
+
+        assert_eq!(quad.len(), 19);
+        assert_sizes(&quad);
+
+        for id in ids {
+            quad.delete_by_id(id);
+        }
+
+        assert_eq!(quad.len(), 1);
+        assert_sizes(&quad);
+    }
+
+    #[test]
+    fn test_sizes_after_bulk_load() {
+        let entries = (0..500)
+            .map(|i| (Point{x: (i % 25) as f64, y: (i / 25) as f64}, i))
+            .collect();
+
+        let quad = Quad::bulk_load(Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 25.0, y: 25.0}), 8, entries).unwrap();
+
+        assert_eq!(quad.len(), 500);
+        assert_eq!(quad.count_within_rectangle(&Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 9.5, y: 9.5})), 100);
+        assert_sizes(&quad);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 2)
+            .with_duplicate_policy(DuplicatePolicy::Count);
+
+        for i in 0..40 {
+            let i = i as f64;
+            quad.insert(&Point{x: i * 2.5, y: 100.0 - i * 2.0}, format!("entry {i}").into_bytes()).unwrap();
+        }
+
+        quad.insert(&Point{x: 5.0, y: 96.0}, vec![]).unwrap();
+        quad.delete_by_id(7);
+
+        let mut bytes = vec![];
+        quad.save_to(&mut bytes).unwrap();
+
+        let mut loaded = Quad::<Vec<u8>>::load_from(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.len(), 39);
+        assert_eq!(loaded.border, quad.border);
+        assert_eq!(loaded.get_by_id(2), quad.get_by_id(2));
+        assert_eq!(loaded.get_by_id(2).unwrap().count, 2);
+        assert!(loaded.get_by_id(7).is_none());
+        assert_eq!(loaded.insert(&Point{x: 1.0, y: 1.0}, vec![]), Ok(40));
+        assert_eq!(loaded.insert(&Point{x: 5.0, y: 96.0}, vec![]), Ok(2));
+        assert_sizes(&loaded);
+    }
+
+    #[test]
+    fn test_snapshot_rejects_bad_input() {
+        let mut quad: Quad<()> = Quad::new();
+        quad.insert(&Point{x: 1.0, y: 1.0}, ()).unwrap();
+
+        let mut bytes = vec![];
+        quad.save_to(&mut bytes).unwrap();
+
+        let truncated = &bytes[..bytes.len() - 4];
+        assert!(matches!(Quad::<()>::load_from(&mut &truncated[..]), Err(SnapshotError::Io(_))));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 99;
+        assert!(matches!(Quad::<()>::load_from(&mut wrong_version.as_slice()), Err(SnapshotError::UnsupportedVersion(99))));
+
+        assert!(matches!(Quad::<()>::load_from(&mut &b"nope, not a tree"[..]), Err(SnapshotError::NotASnapshot)));
+    }
+
+    // Overwrites the i64 or f64 at `offset` in the snapshot of an empty tree
+    fn load_patched(offset: usize, value: [u8; 8]) -> Result<Quad<()>, SnapshotError> {
+        let mut bytes = vec![];
+        Quad::<()>::new().save_to(&mut bytes).unwrap();
+
+        bytes[offset..offset + 8].copy_from_slice(&value);
+
+        return Quad::load_from(&mut bytes.as_slice());
+    }
+
+    // Offsets into the snapshot of the four border coordinates, then capacity
+    // and max depth, which follow the magic and version
+    const TOP_LEFT_X: usize = 8;
+    const BOTTOM_RIGHT_X: usize = 24;
+    const BOTTOM_RIGHT_Y: usize = 32;
+    const CAPACITY: usize = 40;
+    const MAX_DEPTH_OFFSET: usize = 48;
+
+    fn is_corrupt(result: Result<Quad<()>, SnapshotError>) -> bool {
+        return matches!(result, Err(SnapshotError::Corrupt(_)));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_non_positive_capacity() {
+        assert!(is_corrupt(load_patched(CAPACITY, 0i64.to_le_bytes())));
+        assert!(is_corrupt(load_patched(CAPACITY, (-3i64).to_le_bytes())));
+        assert!(load_patched(CAPACITY, 1i64.to_le_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_out_of_range_max_depth() {
+        assert!(is_corrupt(load_patched(MAX_DEPTH_OFFSET, (-1i64).to_le_bytes())));
+        assert!(is_corrupt(load_patched(MAX_DEPTH_OFFSET, (MAX_DEPTH + 1).to_le_bytes())));
+        assert!(load_patched(MAX_DEPTH_OFFSET, 0i64.to_le_bytes()).is_ok());
+        assert!(load_patched(MAX_DEPTH_OFFSET, MAX_DEPTH.to_le_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_non_finite_bounds() {
+        assert!(is_corrupt(load_patched(TOP_LEFT_X, f64::NAN.to_le_bytes())));
+        assert!(is_corrupt(load_patched(BOTTOM_RIGHT_Y, f64::INFINITY.to_le_bytes())));
+        assert!(is_corrupt(load_patched(TOP_LEFT_X, f64::NEG_INFINITY.to_le_bytes())));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_inverted_bounds() {
+        assert!(is_corrupt(load_patched(BOTTOM_RIGHT_X, 0f64.to_le_bytes())));
+        assert!(is_corrupt(load_patched(BOTTOM_RIGHT_X, (-5f64).to_le_bytes())));
+        assert!(is_corrupt(load_patched(BOTTOM_RIGHT_Y, 0f64.to_le_bytes())));
+    }
+
+    struct Annulus {
+        center: Point,
+        inner_radius: f64,
+        outer_radius: f64
+    }
+
+    impl Shape for Annulus {
+        fn intersects(&self, rectangle: &Rectangle) -> bool {
+            let outer = Circle{
+                center: self.center,
+                radius: self.outer_radius
+            };
+
+            return outer.intersects(rectangle);
+        }
+
+        fn contains(&self, point: &Point) -> bool {
+            let distance = self.center.distance(point);
+
+            return distance >= self.inner_radius && distance <= self.outer_radius;
+        }
+    }
+
+    #[test]
+    fn test_query_with_custom_shape() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        quad.insert(&Point{x: 50.0, y: 50.0}, 1).unwrap();
+        quad.insert(&Point{x: 60.0, y: 50.0}, 2).unwrap();
+        quad.insert(&Point{x: 50.0, y: 35.0}, 3).unwrap();
+        quad.insert(&Point{x: 90.0, y: 90.0}, 4).unwrap();
+
+        let annulus = Annulus{
+            center: Point{x: 50.0, y: 50.0},
+            inner_radius: 5.0,
+            outer_radius: 20.0
+        };
+
+        let mut values = quad.query(&annulus)
+            .iter()
+            .map(|e| e.value)
+            .collect::<Vec<i32>>();
+        values.sort();
+
+        assert_eq!(values, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_delete_collapses_nodes() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        let mut points = vec![];
+        for i in 0..50 {
+            points.push(Point{
+                x: (i * 7 % 100) as f64,
+                y: (i * 13 % 100) as f64
+            });
+        }
+
+        for point in &points {
+            quad.insert(point, ()).unwrap();
+        }
+
+        assert!(quad.node_count() > 1);
+
+        for point in &points[3..] {
+            quad.delete(point);
+        }
+
+        assert_eq!(quad.node_count(), 1);
+        assert!(quad.is_leaf);
+        assert_eq!(quad.entries.len(), 3);
+
+        for point in &points[..3] {
+            assert!(quad.search(point).is_some());
+        }
+    }
+
+    #[test]
+    fn test_churn_keeps_tree_proportional() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 2);
+
+        for round in 0..5 {
+            let mut ids = vec![];
+            for i in 0..40 {
+                let point = Point{
+                    x: ((i * 17 + round) % 100) as f64,
+                    y: ((i * 31 + round) % 100) as f64
+                };
+
+                ids.push(quad.insert(&point, ()).unwrap());
+            }
+
+            assert!(quad.node_count() > 1);
+
+            for id in ids {
+                assert!(quad.delete_by_id(id).is_some());
+            }
+
+            assert_eq!(quad.node_count(), 1);
+        }
+
+        let id = quad.insert(&Point{x: 1.0, y: 1.0}, ()).unwrap();
+        quad.insert(&Point{x: 99.0, y: 99.0}, ()).unwrap();
+        quad.insert(&Point{x: 1.0, y: 99.0}, ()).unwrap();
+
+        assert_eq!(quad.node_count(), 5);
+
+        quad.move_by_id(id, &Point{x: 99.0, y: 1.0});
+
+        assert_eq!(quad.node_count(), 5);
+    }
+
+    #[test]
+    fn test_max_depth_with_duplicate_points() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_max_depth(4);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        for i in 0..100 {
+            quad.insert(&point, i).unwrap();
+        }
+
+        let circle = Circle{
+            center: point,
+            radius: 1.0
+        };
+
+        assert_eq!(quad.find_within_range(&circle).len(), 100);
+        assert_eq!(quad.node_count(), 17);
+        assert_eq!(quad.delete(&point).len(), 100);
+        assert_eq!(quad.node_count(), 1);
+    }
+
+    #[test]
+    fn test_change_settings() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 4);
+
+        // Two points in each quadrant, so the root splits exactly once
+        for (i, (x, y)) in [(25.0, 25.0), (75.0, 25.0), (25.0, 75.0), (75.0, 75.0)].into_iter().enumerate() {
+            quad.insert(&Point{x, y}, i).unwrap();
+            quad.insert(&Point{x: x + 5.0, y: y + 5.0}, i + 4).unwrap();
+        }
+
+        assert_eq!(quad.node_count(), 5);
+
+        quad.set_capacity(100);
+        quad.set_max_depth(2);
+        quad.set_duplicate_policy(DuplicatePolicy::Reject);
+
+        assert_eq!(quad.top_left_quad.as_ref().unwrap().capacity(), 100);
+        assert_eq!(quad.top_left_quad.as_ref().unwrap().max_depth(), 2);
+
+        // The existing split is kept, but the larger capacity stops new ones
+        for i in 0..40 {
+            quad.insert(&Point{x: i as f64 + 0.5, y: 1.0}, i).unwrap();
+        }
+
+        assert_eq!(quad.node_count(), 5);
+        assert_eq!(quad.insert(&Point{x: 25.0, y: 25.0}, 0), Err(InsertError::Duplicate(0)));
+        assert_sizes(&quad);
+    }
+
+    #[test]
+    fn test_reject_duplicates() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_duplicate_policy(DuplicatePolicy::Reject);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        let id = quad.insert(&point, "first").unwrap();
+
+        assert_eq!(quad.insert(&point, "second"), Err(InsertError::Duplicate(id)));
+        assert_eq!(quad.search(&point), Some(&"first"));
+    }
+
+    #[test]
+    fn test_count_duplicates() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_duplicate_policy(DuplicatePolicy::Count);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        let id = quad.insert(&point, ()).unwrap();
+        quad.insert(&Point{x: 90.0, y: 90.0}, ()).unwrap();
+
+        assert_eq!(quad.insert(&point, ()), Ok(id));
+        assert_eq!(quad.insert(&point, ()), Ok(id));
+        assert_eq!(quad.get_by_id(id).unwrap().count, 3);
+        assert_eq!(quad.node_count(), 5);
+    }
+
+    #[test]
+    fn test_delete_counted_duplicates() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_duplicate_policy(DuplicatePolicy::Count);
+
+        let point = Point{
+            x: 5.0,
+            y: 5.0
+        };
+
+        let id = quad.insert(&point, "first").unwrap();
+        quad.insert(&point, "second").unwrap();
+        quad.insert(&point, "third").unwrap();
+
+        assert_eq!(quad.delete(&point), vec!["first"]);
+        assert_eq!(quad.get_by_id(id).unwrap().count, 2);
+        assert_eq!(quad.delete(&point), vec!["first"]);
+        assert_eq!(quad.get_by_id(id).unwrap().count, 1);
+        assert_eq!(quad.len(), 1);
+
+        assert_eq!(quad.delete(&point), vec!["first"]);
+        assert!(quad.get_by_id(id).is_none());
+        assert!(quad.is_empty());
+        assert!(quad.delete(&point).is_empty());
+    }
+
+    #[test]
+    fn test_out_of_bounds_insert_without_growth() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1);
+
+        assert_eq!(quad.insert(&Point{x: 150.0, y: 50.0}, ()), Err(InsertError::OutOfBounds));
+        assert_eq!(quad.delete(&Point{x: 150.0, y: 50.0}).len(), 0);
+        assert_eq!(quad.border, Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 100.0, y: 100.0}));
+    }
+
+    #[test]
+    fn test_growable_root() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 1)
+            .with_growable(true);
+
+        let inside = Point{
+            x: 10.0,
+            y: 10.0
+        };
+        let right = Point{
+            x: 150.0,
+            y: 50.0
+        };
+        let far_top_left = Point{
+            x: -350.0,
+            y: -20.0
+        };
+
+        quad.insert(&inside, 1).unwrap();
+        quad.insert(&Point{x: 90.0, y: 90.0}, 2).unwrap();
+
+        assert!(quad.insert(&right, 3).is_ok());
+        assert_eq!(quad.border, Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 200.0, y: 200.0}));
+
+        assert!(quad.insert(&far_top_left, 4).is_ok());
+        assert!(quad.border.contains(&far_top_left));
+        assert!(quad.border.contains(&right));
+
+        assert_eq!(quad.search(&inside), Some(&1));
+        assert_eq!(quad.search(&right), Some(&3));
+        assert_eq!(quad.search(&far_top_left), Some(&4));
+
+        let circle = Circle{
+            center: Point{x: 0.0, y: 0.0},
+            radius: 1000.0
+        };
+
+        assert_eq!(quad.find_within_range(&circle).len(), 4);
+        assert_eq!(quad.delete(&far_top_left), vec![4]);
+        assert_eq!(quad.search(&inside), Some(&1));
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        let bounds = Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 100.0, y: 100.0});
+
+        let mut entries = vec![];
+        for i in 0..1000 {
+            entries.push((Point{
+                x: (i * 37 % 1000) as f64 / 10.0,
+                y: (i * 91 % 1000) as f64 / 10.0
+            }, i));
+        }
+
+        let mut inserted = Quad::from(bounds.top_left, bounds.bottom_right, 8);
+        for (point, value) in &entries {
+            inserted.insert(point, *value).unwrap();
+        }
+
+        let loaded = Quad::bulk_load(bounds, 8, entries.clone()).unwrap();
+
+        for (point, value) in &entries {
+            assert_eq!(loaded.get_by_id(*value as u64).unwrap().point, *point);
+        }
+
+        let circle = Circle{
+            center: Point{x: 30.0, y: 60.0},
+            radius: 25.0
+        };
+
+        let mut expected = inserted.find_within_range(&circle)
+            .iter()
+            .map(|e| e.value)
+            .collect::<Vec<i32>>();
+        let mut actual = loaded.find_within_range(&circle)
+            .iter()
+            .map(|e| e.value)
+            .collect::<Vec<i32>>();
+        expected.sort();
+        actual.sort();
+
+        assert_eq!(actual, expected);
+        assert!(loaded.node_count() <= inserted.node_count());
+    }
+
+    #[test]
+    fn test_bulk_load_out_of_bounds() {
+        let bounds = Rectangle::from(&Point{x: 0.0, y: 0.0}, &Point{x: 100.0, y: 100.0});
+
+        let entries = vec![
+            (Point{x: 5.0, y: 5.0}, ()),
+            (Point{x: 500.0, y: 5.0}, ())
+        ];
+
+        assert_eq!(Quad::bulk_load(bounds, 4, entries).err(), Some(InsertError::OutOfBounds));
+    }
+
+    #[test]
+    fn test_insert_bulk_into_populated_tree() {
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 100.0, y: 100.0}, 2);
+
+        quad.insert(&Point{x: 5.0, y: 5.0}, 0).unwrap();
+
+        let results = quad.insert_bulk(vec![
+            (Point{x: 10.0, y: 10.0}, 1),
+            (Point{x: 500.0, y: 10.0}, 2),
+            (Point{x: 90.0, y: 90.0}, 3)
+        ]);
+
+        assert_eq!(results, vec![Ok(1), Err(InsertError::OutOfBounds), Ok(2)]);
+        assert_eq!(quad.search(&Point{x: 90.0, y: 90.0}), Some(&3));
+    }
+}
@@ -0,0 +1,60 @@
+use std::slice::Iter;
+
+use crate::entry::Entry;
+use crate::quad::Quad;
+use crate::shape::Shape;
+
+/// Lazily walks the nodes intersecting a shape, yielding matching entries one at a time
+pub struct QueryIter<'a, 's, V, S> {
+    shape: &'s S,
+
+    // Nodes still to visit, paired with whether the shape fully covers them
+    stack: Vec<(&'a Quad<V>, bool)>,
+
+    entries: Iter<'a, Entry<V>>,
+    covered: bool
+}
+
+impl<'a, 's, V, S: Shape> QueryIter<'a, 's, V, S> {
+    pub(crate) fn new(quad: &'a Quad<V>, shape: &'s S) -> QueryIter<'a, 's, V, S> {
+        return QueryIter {
+            shape,
+            stack: vec![(quad, false)],
+            entries: [].iter(),
+            covered: false
+        }
+    }
+}
+
+impl<'a, V, S: Shape> Iterator for QueryIter<'a, '_, V, S> {
+    type Item = &'a Entry<V>;
+
+    fn next(&mut self) -> Option<&'a Entry<V>> {
+        loop {
+            for entry in self.entries.by_ref() {
+                if self.covered || self.shape.contains(&entry.point) {
+                    return Some(entry)
+                }
+            }
+
+            let (quad, covered) = self.stack.pop()?;
+
+            if !covered && !self.shape.intersects(&quad.border) {
+                continue
+            }
+
+            let covered = covered || self.shape.contains_rect(&quad.border);
+
+            if quad.is_leaf {
+                self.entries = quad.entries.iter();
+                self.covered = covered;
+                continue
+            }
+
+            // Pushed in reverse so children are visited top left first
+            for child in [&quad.bottom_right_quad, &quad.bottom_left_quad, &quad.top_right_quad, &quad.top_left_quad] {
+                self.stack.push((child.as_ref().unwrap(), covered));
+            }
+        }
+    }
+}
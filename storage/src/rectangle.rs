@@ -1,4 +1,4 @@
-use crate::point::Point;
+use crate::{point::Point, shape::Shape};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Rectangle {
@@ -26,5 +26,47 @@ impl Rectangle {
             bottom_right: *bottom_right
         }
     }
+
+    pub fn intersects(&self, rectangle: &Rectangle) -> bool {
+        return self.top_left.x <= rectangle.bottom_right.x && rectangle.top_left.x <= self.bottom_right.x &&
+                self.top_left.y <= rectangle.bottom_right.y && rectangle.top_left.y <= self.bottom_right.y
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        return point.x >= self.top_left.x && point.x <= self.bottom_right.x &&
+                point.y >= self.top_left.y && point.y <= self.bottom_right.y
+    }
+
+    pub fn corners(&self) -> [Point; 4] {
+        return [
+            self.top_left,
+            Point { x: self.bottom_right.x, y: self.top_left.y },
+            self.bottom_right,
+            Point { x: self.top_left.x, y: self.bottom_right.y }
+        ]
+    }
+
+    /// Shortest distance from the point to any point of the rectangle, zero when inside
+    pub fn distance(&self, point: &Point) -> f64 {
+        let closest = Point {
+            x: f64::max(self.top_left.x, f64::min(point.x, self.bottom_right.x)),
+            y: f64::max(self.top_left.y, f64::min(point.y, self.bottom_right.y))
+        };
+
+        return closest.distance(point);
+    }
 }
 
+impl Shape for Rectangle {
+    fn intersects(&self, rectangle: &Rectangle) -> bool {
+        return Rectangle::intersects(self, rectangle);
+    }
+
+    fn contains(&self, point: &Point) -> bool {
+        return Rectangle::contains(self, point);
+    }
+
+    fn contains_rect(&self, rectangle: &Rectangle) -> bool {
+        return self.contains(&rectangle.top_left) && self.contains(&rectangle.bottom_right);
+    }
+}
@@ -0,0 +1,15 @@
+use crate::{point::Point, rectangle::Rectangle};
+
+/// A region that can be used to query a `Quad`
+pub trait Shape {
+    /// Whether any part of the rectangle overlaps the shape. Used to prune quadrants.
+    fn intersects(&self, rectangle: &Rectangle) -> bool;
+
+    fn contains(&self, point: &Point) -> bool;
+
+    /// Whether the whole rectangle lies within the shape, letting a quadrant be
+    /// accepted without testing its points. Returning `false` is always correct.
+    fn contains_rect(&self, _rectangle: &Rectangle) -> bool {
+        return false;
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt;
+use std::io;
+
+/// Reason a snapshot could not be written or read by `Quad::save_to` / `Quad::load_from`
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The underlying reader or writer failed, including on truncated input
+    Io(io::Error),
+    /// The input does not start with the snapshot magic bytes
+    NotASnapshot,
+    /// The snapshot was written by a format version this build cannot read
+    UnsupportedVersion(u32),
+    /// The snapshot decoded but describes an impossible tree
+    Corrupt(String)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot i/o failed: {e}"),
+            SnapshotError::NotASnapshot => write!(f, "input is not a quad tree snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {v}"),
+            SnapshotError::Corrupt(details) => write!(f, "snapshot is corrupt: {details}")
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(e) => return Some(e),
+            _ => return None
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        return SnapshotError::Io(e);
+    }
+}
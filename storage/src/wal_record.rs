@@ -0,0 +1,106 @@
+use std::io::{self, Read, Write};
+
+use crate::codec::Codec;
+use crate::point::Point;
+use crate::quad::Quad;
+
+/// A single mutation as stored in the write-ahead log
+#[derive(Clone, Debug, PartialEq)]
+pub enum WalRecord<V> {
+    /// An insert, with the caller chosen id if there was one
+    Insert { id: Option<u64>, point: Point, value: V },
+    Delete { point: Point },
+    DeleteById { id: u64 },
+    Move { id: u64, point: Point }
+}
+
+impl<V> WalRecord<V> {
+    /// Re-applies the mutation. Ids are assigned deterministically, so replaying
+    /// the log in order on top of the snapshot it follows rebuilds the same tree.
+    /// Mutations that failed originally fail again and are ignored.
+    pub fn apply(self, quad: &mut Quad<V>) where V: Clone {
+        match self {
+            WalRecord::Insert { id: Some(id), point, value } => {
+                let _ = quad.insert_with_id(id, &point, value);
+            }
+            WalRecord::Insert { id: None, point, value } => {
+                let _ = quad.insert(&point, value);
+            }
+            WalRecord::Delete { point } => {
+                quad.delete(&point);
+            }
+            WalRecord::DeleteById { id } => {
+                quad.delete_by_id(id);
+            }
+            WalRecord::Move { id, point } => {
+                quad.move_by_id(id, &point);
+            }
+        }
+    }
+}
+
+fn encode_point<W: Write>(point: &Point, writer: &mut W) -> io::Result<()> {
+    point.x.encode(writer)?;
+
+    return point.y.encode(writer);
+}
+
+fn decode_point<R: Read>(reader: &mut R) -> io::Result<Point> {
+    return Ok(Point{
+        x: f64::decode(reader)?,
+        y: f64::decode(reader)?
+    });
+}
+
+impl<V: Codec> Codec for WalRecord<V> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            WalRecord::Insert { id, point, value } => {
+                1u8.encode(writer)?;
+                id.is_some().encode(writer)?;
+                id.unwrap_or(0).encode(writer)?;
+                encode_point(point, writer)?;
+                value.encode(writer)?;
+            }
+            WalRecord::Delete { point } => {
+                2u8.encode(writer)?;
+                encode_point(point, writer)?;
+            }
+            WalRecord::DeleteById { id } => {
+                3u8.encode(writer)?;
+                id.encode(writer)?;
+            }
+            WalRecord::Move { id, point } => {
+                4u8.encode(writer)?;
+                id.encode(writer)?;
+                encode_point(point, writer)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<WalRecord<V>> {
+        match u8::decode(reader)? {
+            1 => {
+                let has_id = bool::decode(reader)?;
+                let id = u64::decode(reader)?;
+
+                return Ok(WalRecord::Insert {
+                    id: if has_id { Some(id) } else { None },
+                    point: decode_point(reader)?,
+                    value: V::decode(reader)?
+                });
+            }
+            2 => return Ok(WalRecord::Delete { point: decode_point(reader)? }),
+            3 => return Ok(WalRecord::DeleteById { id: u64::decode(reader)? }),
+            4 => {
+                return Ok(WalRecord::Move {
+                    id: u64::decode(reader)?,
+                    point: decode_point(reader)?
+                });
+            }
+            t => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown log record tag {t}")))
+        }
+    }
+}
@@ -0,0 +1,542 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::codec::Codec;
+use crate::fsync_policy::FsyncPolicy;
+use crate::quad::Quad;
+use crate::snapshot_error::SnapshotError;
+use crate::wal_record::WalRecord;
+
+/// Append-only log of mutations applied since the last snapshot.
+///
+/// Each record is framed as `length u32 | crc32 u32 | record`, little endian.
+/// A frame that is cut short or fails its checksum marks the end of the log;
+/// it can only be the tail of an append that never completed.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: File,
+    policy: FsyncPolicy,
+    last_sync: Instant,
+    // Set when committed records have not been synced yet
+    unsynced: bool,
+    len: u64,
+    // Length of the log as of the last commit. A failed append or commit cuts
+    // the log back to it, so a batch is replayed either whole or not at all.
+    committed_len: u64,
+    // Set when that cut failed. The log may then end in a partial frame,
+    // which would hide anything appended after it from replay.
+    failed: bool
+}
+
+/// Outcome of `WriteAheadLog::replay`
+#[derive(Debug, Default, PartialEq)]
+pub struct Replay {
+    /// Records applied to the tree
+    pub records: usize,
+    /// Bytes of torn or corrupt tail that were cut from the log
+    pub discarded: u64
+}
+
+// While a checkpoint is in progress the log being retired is kept here
+fn retired_path(path: &Path) -> PathBuf {
+    let mut retired = path.as_os_str().to_owned();
+    retired.push(".old");
+
+    return PathBuf::from(retired);
+}
+
+fn temp_path(snapshot: &Path) -> PathBuf {
+    let mut temp = snapshot.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    return PathBuf::from(temp);
+}
+
+// Renames are only durable once the directory holding them is synced
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new(".")
+    };
+
+    return File::open(parent)?.sync_all();
+}
+
+// Reads the next intact frame, or None at the end of the log or at a torn frame
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; 8];
+    let mut filled = 0;
+
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) => return Ok(None),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)
+        }
+    }
+
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+    let mut record = vec![];
+    reader.take(len).read_to_end(&mut record)?;
+
+    if (record.len() as u64) < len || crc32fast::hash(&record) != checksum {
+        return Ok(None)
+    }
+
+    return Ok(Some(record));
+}
+
+// Length of the log up to the first torn frame
+fn intact_len(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut len = 0;
+
+    while let Some(record) = read_frame(&mut reader)? {
+        len += 8 + record.len() as u64;
+    }
+
+    return Ok(len);
+}
+
+impl WriteAheadLog {
+    /// Opens the log for appending, creating it if needed. Replay an existing
+    /// log with `replay` first.
+    pub fn open(path: &Path, policy: FsyncPolicy) -> io::Result<WriteAheadLog> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let len = file.metadata()?.len();
+
+        return Ok(WriteAheadLog {
+            path: path.to_path_buf(),
+            file,
+            policy,
+            last_sync: Instant::now(),
+            unsynced: false,
+            len,
+            committed_len: len,
+            failed: false
+        });
+    }
+
+    /// Changes the fsync policy applied by future commits
+    pub fn set_policy(&mut self, policy: FsyncPolicy) {
+        self.policy = policy;
+    }
+
+    /// Writes a record to the log. It is not durable until `commit` returns.
+    /// On failure every record appended since the last commit is dropped.
+    pub fn append<V: Codec>(&mut self, record: &WalRecord<V>) -> io::Result<()> {
+        self.check_failed()?;
+
+        let mut bytes = vec![];
+        let result = record.encode(&mut bytes).and_then(|_| {
+            let mut frame = Vec::with_capacity(bytes.len() + 8);
+            frame.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&crc32fast::hash(&bytes).to_le_bytes());
+            frame.extend_from_slice(&bytes);
+
+            self.file.write_all(&frame)?;
+            self.len += frame.len() as u64;
+
+            return Ok(());
+        });
+
+        return result.map_err(|e| self.roll_back(e));
+    }
+
+    /// Makes the appended records durable as far as the fsync policy requires.
+    /// Callers acknowledge a mutation only after this returns. On failure the
+    /// records appended since the last commit are dropped.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.check_failed()?;
+
+        let result = match self.policy {
+            FsyncPolicy::Always => self.sync(),
+            FsyncPolicy::Interval(interval) if self.last_sync.elapsed() >= interval => self.sync(),
+            _ => {
+                self.unsynced = true;
+                Ok(())
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                self.committed_len = self.len;
+                return Ok(());
+            },
+            Err(e) => return Err(self.roll_back(e))
+        }
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.last_sync = Instant::now();
+        self.unsynced = false;
+
+        return Ok(());
+    }
+
+    /// Syncs records committed under `FsyncPolicy::Interval` once the interval
+    /// has passed. Commits only sync when they come due themselves, so without
+    /// this the last writes before a quiet spell would stay unsynced.
+    pub fn sync_due(&mut self) -> io::Result<()> {
+        match self.policy {
+            FsyncPolicy::Interval(interval) if self.unsynced && self.last_sync.elapsed() >= interval => return self.sync(),
+            _ => return Ok(())
+        }
+    }
+
+    fn check_failed(&self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("the write-ahead log could not be repaired after a failed write"));
+        }
+
+        return Ok(());
+    }
+
+    // Cuts the log back to the last commit after `error`, which is returned
+    fn roll_back(&mut self, error: io::Error) -> io::Error {
+        match self.file.set_len(self.committed_len) {
+            Ok(()) => self.len = self.committed_len,
+            Err(_) => self.failed = true
+        }
+
+        return error;
+    }
+
+    /// Writes a snapshot of the tree and empties the log. Must be called with
+    /// writers excluded, so the snapshot holds exactly the logged mutations.
+    ///
+    /// The log is retired before the snapshot is committed. A crash part way
+    /// leaves either the old snapshot plus the retired log, or the new
+    /// snapshot; `replay` tells them apart by the uncommitted snapshot file.
+    pub fn checkpoint<V: Codec>(&mut self, quad: &Quad<V>, snapshot: &Path) -> Result<(), SnapshotError> {
+        let temp = temp_path(snapshot);
+        let retired = retired_path(&self.path);
+
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        quad.save_to(&mut writer)?;
+        writer.flush()?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        self.sync()?;
+        fs::rename(&self.path, &retired)?;
+        sync_parent(&self.path)?;
+
+        if let Err(e) = fs::rename(&temp, snapshot).and_then(|_| sync_parent(snapshot)) {
+            fs::rename(&retired, &self.path)?;
+            return Err(e.into());
+        }
+
+        *self = WriteAheadLog::open(&self.path, self.policy)?;
+        sync_parent(&self.path)?;
+        fs::remove_file(&retired)?;
+
+        return Ok(());
+    }
+
+    /// Applies the log at `path` to a tree restored from `snapshot`, or to a
+    /// fresh tree when snapshots are not in use. Finishes or rolls back an
+    /// interrupted checkpoint and cuts any torn tail off the log.
+    pub fn replay<V: Codec + Clone>(path: &Path, snapshot: Option<&Path>, quad: &mut Quad<V>) -> io::Result<Replay> {
+        let retired = retired_path(path);
+
+        if retired.exists() {
+            let committed = match snapshot {
+                Some(s) => !temp_path(s).exists(),
+                None => false
+            };
+
+            if committed {
+                fs::remove_file(&retired)?;
+            }
+            else {
+                // The snapshot never replaced the old one, so the retired log
+                // is still needed. Anything logged since goes on after it.
+                let retired_len = intact_len(&retired)?;
+                let mut log = OpenOptions::new().write(true).open(&retired)?;
+                log.set_len(retired_len)?;
+
+                if path.exists() {
+                    let tail_len = intact_len(path)?;
+                    let mut tail = File::open(path)?.take(tail_len);
+
+                    log.seek(SeekFrom::End(0))?;
+                    io::copy(&mut tail, &mut log)?;
+                }
+
+                log.sync_all()?;
+                fs::rename(&retired, path)?;
+                sync_parent(path)?;
+            }
+        }
+
+        if let Some(s) = snapshot {
+            let temp = temp_path(s);
+
+            if temp.exists() {
+                fs::remove_file(temp)?;
+            }
+        }
+
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Replay::default()),
+            Err(e) => return Err(e)
+        };
+
+        let total = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut replay = Replay::default();
+        let mut intact = 0;
+
+        while let Some(bytes) = read_frame(&mut reader)? {
+            WalRecord::<V>::decode(&mut bytes.as_slice())?.apply(quad);
+
+            intact += 8 + bytes.len() as u64;
+            replay.records += 1;
+        }
+
+        if intact < total {
+            let log = OpenOptions::new().write(true).open(path)?;
+            log.set_len(intact)?;
+            log.sync_all()?;
+
+            replay.discarded = total - intact;
+        }
+
+        return Ok(replay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    use crate::codec::Codec;
+    use crate::fsync_policy::FsyncPolicy;
+    use crate::point::Point;
+    use crate::quad::Quad;
+    use crate::wal_record::WalRecord;
+
+    use super::{Replay, WriteAheadLog};
+
+    fn insert(x: f64, y: f64) -> WalRecord<Vec<u8>> {
+        return WalRecord::Insert { id: None, point: Point{x, y}, value: format!("{x},{y}").into_bytes() };
+    }
+
+    fn write_log(path: &Path, records: &[WalRecord<Vec<u8>>]) {
+        let mut log = WriteAheadLog::open(path, FsyncPolicy::Always).unwrap();
+
+        for record in records {
+            log.append(record).unwrap();
+        }
+
+        log.commit().unwrap();
+    }
+
+    fn recover(path: &Path, snapshot: Option<&Path>) -> (Quad<Vec<u8>>, Replay) {
+        let mut quad = match snapshot {
+            Some(s) if s.exists() => Quad::load_from(&mut File::open(s).unwrap()).unwrap(),
+            _ => Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, 2)
+        };
+
+        let replay = WriteAheadLog::replay(path, snapshot, &mut quad).unwrap();
+
+        return (quad, replay);
+    }
+
+    #[test]
+    fn test_replay_rebuilds_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.wal");
+
+        let records = vec![
+            insert(1.0, 1.0),
+            insert(2.0, 2.0),
+            insert(3.0, 3.0),
+            WalRecord::Insert { id: Some(42), point: Point{x: 4.0, y: 4.0}, value: vec![] },
+            WalRecord::Delete { point: Point{x: 2.0, y: 2.0} },
+            WalRecord::Move { id: 0, point: Point{x: 9.0, y: 9.0} },
+            WalRecord::DeleteById { id: 2 }
+        ];
+        write_log(&path, &records);
+
+        let mut expected = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, 2);
+        for record in records {
+            record.apply(&mut expected);
+        }
+
+        let (mut quad, replay) = recover(&path, None);
+
+        assert_eq!(replay, Replay{records: 7, discarded: 0});
+        assert_eq!(quad.len(), 2);
+        assert_eq!(quad.get_by_id(0), expected.get_by_id(0));
+        assert_eq!(quad.get_by_id(0).unwrap().point, Point{x: 9.0, y: 9.0});
+        assert!(quad.get_by_id(42).is_some());
+        assert_eq!(quad.insert(&Point{x: 5.0, y: 5.0}, vec![]), Ok(43));
+    }
+
+    #[test]
+    fn test_replay_discards_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.wal");
+
+        write_log(&path, &[insert(1.0, 1.0), insert(2.0, 2.0), insert(3.0, 3.0)]);
+
+        // Simulate a crash part way through writing the last record
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 5).unwrap();
+
+        let (quad, replay) = recover(&path, None);
+
+        assert_eq!(quad.len(), 2);
+        assert_eq!(replay.records, 2);
+        assert!(replay.discarded > 0);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len - 5 - replay.discarded);
+
+        // Appends after the cut are readable again
+        write_log(&path, &[insert(4.0, 4.0)]);
+
+        let (quad, replay) = recover(&path, None);
+
+        assert_eq!(quad.len(), 3);
+        assert_eq!(replay, Replay{records: 3, discarded: 0});
+    }
+
+    #[test]
+    fn test_replay_stops_at_bad_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.wal");
+
+        write_log(&path, &[insert(1.0, 1.0), insert(2.0, 2.0)]);
+
+        let len = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(len - 1)).unwrap();
+        file.write_all(b"?").unwrap();
+
+        let (quad, replay) = recover(&path, None);
+
+        assert_eq!(quad.len(), 1);
+        assert_eq!(replay.records, 1);
+        assert!(replay.discarded > 0);
+    }
+
+    // A payload that fails to encode, standing in for a write that errors
+    struct Unwritable;
+
+    impl Codec for Unwritable {
+        fn encode<W: Write>(&self, _writer: &mut W) -> io::Result<()> {
+            return Err(io::Error::other("no space left on device"));
+        }
+
+        fn decode<R: Read>(_reader: &mut R) -> io::Result<Unwritable> {
+            return Ok(Unwritable);
+        }
+    }
+
+    #[test]
+    fn test_failed_batch_is_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.wal");
+
+        let mut log = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        log.append(&insert(1.0, 1.0)).unwrap();
+        log.commit().unwrap();
+
+        let committed = fs::metadata(&path).unwrap().len();
+
+        // The batch fails part way, after one of its records was written
+        log.append(&insert(2.0, 2.0)).unwrap();
+        assert!(log.append(&WalRecord::Insert { id: None, point: Point{x: 3.0, y: 3.0}, value: Unwritable }).is_err());
+        assert_eq!(fs::metadata(&path).unwrap().len(), committed);
+
+        // Later batches are still replayed
+        log.append(&insert(4.0, 4.0)).unwrap();
+        log.commit().unwrap();
+
+        let (quad, replay) = recover(&path, None);
+
+        assert_eq!(replay, Replay{records: 2, discarded: 0});
+        assert_eq!(quad.iter().map(|e| e.point.x).collect::<Vec<f64>>(), vec![1.0, 4.0]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_unrepairable_log_refuses_writes() {
+        // Writes to /dev/full fail, and so does cutting it back
+        let mut log = WriteAheadLog::open(Path::new("/dev/full"), FsyncPolicy::Never).unwrap();
+
+        assert_eq!(log.append(&insert(1.0, 1.0)).unwrap_err().kind(), ErrorKind::StorageFull);
+        assert_eq!(log.append(&insert(2.0, 2.0)).unwrap_err().kind(), ErrorKind::Other);
+        assert!(log.commit().is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_truncates_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.wal");
+        let snapshot = dir.path().join("tree.snapshot");
+
+        write_log(&path, &[insert(1.0, 1.0), insert(2.0, 2.0)]);
+        let (quad, _) = recover(&path, Some(&snapshot));
+
+        let mut log = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        log.checkpoint(&quad, &snapshot).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        log.append(&insert(3.0, 3.0)).unwrap();
+        log.commit().unwrap();
+
+        let (quad, replay) = recover(&path, Some(&snapshot));
+
+        assert_eq!(replay.records, 1);
+        assert_eq!(quad.len(), 3);
+    }
+
+    #[test]
+    fn test_replay_recovers_interrupted_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.wal");
+        let snapshot = dir.path().join("tree.snapshot");
+        let retired = dir.path().join("tree.wal.old");
+        let temp = dir.path().join("tree.snapshot.tmp");
+
+        let mut quad = Quad::from(Point{x: 0.0, y: 0.0}, Point{x: 10.0, y: 10.0}, 2);
+        quad.insert(&Point{x: 1.0, y: 1.0}, vec![]).unwrap();
+        quad.save_to(&mut File::create(&snapshot).unwrap()).unwrap();
+
+        // Crashed after retiring the log but before committing the snapshot
+        write_log(&retired, &[insert(2.0, 2.0), insert(3.0, 3.0)]);
+        fs::write(&temp, b"partial").unwrap();
+
+        let (quad, replay) = recover(&path, Some(&snapshot));
+
+        assert_eq!(replay.records, 2);
+        assert_eq!(quad.len(), 3);
+        assert!(!retired.exists());
+        assert!(!temp.exists());
+
+        // Crashed after committing the snapshot but before removing the retired log
+        quad.save_to(&mut File::create(&snapshot).unwrap()).unwrap();
+        fs::rename(&path, &retired).unwrap();
+
+        let (quad, replay) = recover(&path, Some(&snapshot));
+
+        assert_eq!(replay.records, 0);
+        assert_eq!(quad.len(), 3);
+        assert!(!retired.exists());
+    }
+}